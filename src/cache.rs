@@ -0,0 +1,62 @@
+use crate::util;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// A response body together with the ETag it was sent with.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Entry {
+    pub etag: String,
+    pub body: Value,
+}
+
+/// Stores responses in one file per url so that they can be requested again conditionally.
+pub struct ResponseCache {
+    directory: String,
+}
+
+impl ResponseCache {
+    pub fn new(directory: &str) -> ResponseCache {
+        ResponseCache {
+            directory: directory.to_string(),
+        }
+    }
+
+    /// Urls are hashed because they contain the api key.
+    fn path(&self, url: &str) -> std::path::PathBuf {
+        std::path::Path::new(&self.directory).join(format!("{:016x}.json", fnv1a(url.as_bytes())))
+    }
+
+    /// A missing or unreadable entry is treated as not cached.
+    pub fn get(&self, url: &str) -> Option<Entry> {
+        let text = std::fs::read_to_string(self.path(url)).ok()?;
+        serde_json::from_str(&text).ok()
+    }
+
+    pub fn put(&self, url: &str, entry: &Entry) -> Result<(), String> {
+        std::fs::create_dir_all(&self.directory)
+            .map_err(|err| format!("failed to create {}: {}", self.directory, err))?;
+        let text = serde_json::to_string(entry)
+            .map_err(|err| format!("failed to serialize cache entry: {}", err))?;
+        util::write_atomic(self.path(url), text.as_bytes(), false)
+    }
+}
+
+/// 64 bit FNV-1a hash. Unlike the hasher of the standard library, it stays the same across Rust
+/// releases so that the cache files are found again.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_is_stable() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+}
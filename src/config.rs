@@ -1,94 +1,1121 @@
-use serde::{Deserialize, Serialize};
+use crate::format::{self, Format};
+use crate::util;
+use crate::youtube;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{Map, Value};
 
 pub const PLAYLISTS_FILE: &str = "playlists.json";
-pub const PLAYLISTS_BACKUP_FILE: &str = "playlists_backup.json";
 pub const CONFIG_FILE: &str = "config.json";
-pub const CONFIG_BACKUP_FILE: &str = "config_backup.json";
+pub const SECRETS_FILE: &str = "secrets.json";
+pub const STATE_FILE: &str = "state.json";
+pub const SQLITE_FILE: &str = "state.sqlite";
+pub const BACKUP_DIRECTORY: &str = "backups";
+/// Youtube api responses for conditional requests.
+pub const CACHE_DIRECTORY: &str = "cache";
+/// How many backups to keep per file.
+pub const BACKUP_COUNT: usize = 10;
+/// How many uploaded tracks are added to a Soundcloud playlist at once by default.
+pub const DEFAULT_BATCH_SIZE: usize = 10;
 
+/// The path of the config file in whichever supported format exists.
+pub fn config_path() -> String {
+    format::find(CONFIG_FILE)
+}
+
+/// The path of the playlists file in whichever supported format exists.
+pub fn playlists_path() -> String {
+    format::find(PLAYLISTS_FILE)
+}
+
+/// Upgrades a file from the version equal to its index in a migration list to the next version.
+/// Files without a version field have version 0 and the current version is the length of the
+/// list.
+pub type Migration = fn(&mut Map<String, Value>) -> Result<(), String>;
+
+pub const CONFIG_MIGRATIONS: &[Migration] = &[config_v0_to_v1];
+pub const PLAYLISTS_MIGRATIONS: &[Migration] = &[unversioned_to_v1];
+pub const STATE_MIGRATIONS: &[Migration] = &[unversioned_to_v1];
+
+/// General configuration. The credentials can be left empty in the config file and instead be
+/// provided through the secrets file or environment variables so that the config file can be
+/// committed to version control.
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct Config {
+    #[serde(default)]
+    pub version: usize,
+    #[serde(default)]
     pub soundcloud_client_id: String,
+    #[serde(default)]
     pub soundcloud_client_secret: String,
+    #[serde(default)]
     pub soundcloud_username: String,
+    #[serde(default)]
     pub soundcloud_password: String,
-    pub soundcloud_access_token: Option<String>,
+    #[serde(default)]
     pub youtube_api_key: String,
+    /// Path of the secrets file. Defaults to `SECRETS_FILE` which is only used if it exists.
+    pub secrets_file: Option<String>,
+    /// Where playlist positions, transfer history and tokens are stored.
+    #[serde(default)]
+    pub store: StoreKind,
+    /// Path of the database when `store` is `sqlite`. Defaults to `SQLITE_FILE`.
+    pub sqlite_file: Option<String>,
+    /// How many uploaded tracks are added to the Soundcloud playlist with one update. Defaults to
+    /// `DEFAULT_BATCH_SIZE`.
+    pub batch_size: Option<usize>,
+    /// Set the purchase url of uploaded tracks to the Youtube video.
+    #[serde(default)]
+    pub purchase_url: bool,
+    /// What to do with videos that are longer than the remaining upload time of the Soundcloud
+    /// account.
+    #[serde(default)]
+    pub quota_exceeded: QuotaExceeded,
+    /// How many Youtube api quota units may be used per day. Unlimited if not set.
+    pub youtube_daily_quota: Option<u64>,
+    /// How Youtube playlists are listed.
+    #[serde(default)]
+    pub youtube_backend: YoutubeBackend,
+    /// Soundcloud genres by Youtube category id or name. Uploads from other categories get no
+    /// genre.
+    #[serde(default)]
+    pub genres: std::collections::BTreeMap<String, String>,
+    /// The access token that version 0 kept in the config file. It is moved to the store.
+    #[serde(skip)]
+    pub legacy_access_token: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreKind {
+    /// `playlists.json` and `state.json`
+    #[default]
+    Json,
+    /// A SQLite database. Requires the `sqlite` feature.
+    Sqlite,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum YoutubeBackend {
+    /// The Youtube Data api. Requires `youtube_api_key`.
+    #[default]
+    Api,
+    /// `youtube-dl --flat-playlist`. Works without an api key but does not know when videos were
+    /// published.
+    YoutubeDl,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuotaExceeded {
+    /// Stop working on the playlist and try again on the next run.
+    #[default]
+    Defer,
+    /// Skip the video and continue with the next one.
+    Skip,
+}
+
+/// Credentials that override the ones in the config file. Every field can be set in the secrets
+/// file or through the environment variable with the upper case name of the field.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Credentials {
+    pub soundcloud_client_id: Option<String>,
+    pub soundcloud_client_secret: Option<String>,
+    pub soundcloud_username: Option<String>,
+    pub soundcloud_password: Option<String>,
+    pub youtube_api_key: Option<String>,
+}
+
+/// Data written by the program itself as opposed to configuration written by the user.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct State {
+    #[serde(default)]
+    pub version: usize,
+    pub soundcloud_access_token: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub soundcloud_app_token: Option<AppToken>,
+    #[serde(default)]
+    pub transfers: Vec<Transfer>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skipped: Vec<Skip>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub youtube_quota: Option<QuotaUsage>,
+}
+
+/// A Soundcloud application access token. Soundcloud limits how often these are issued so it is
+/// kept until it expires.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct AppToken {
+    pub access_token: String,
+    /// RFC 3339 timestamp.
+    pub expires_at: String,
+}
+
+impl AppToken {
+    /// Tokens are renewed a few minutes early so that they do not expire during a request.
+    pub fn is_expired(&self) -> bool {
+        chrono::DateTime::parse_from_rfc3339(&self.expires_at)
+            .map(|expires_at| expires_at <= chrono::Utc::now() + chrono::Duration::minutes(5))
+            .unwrap_or(true)
+    }
+}
+
+/// Youtube api quota units used on a day.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct QuotaUsage {
+    /// See `youtube::quota_day`.
+    pub day: String,
+    pub units: u64,
+    /// Youtube reported that the quota of the api key is used up, so no requests are sent until
+    /// the quota resets.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub exhausted: bool,
+}
+
+/// A video that has been uploaded to Soundcloud.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Transfer {
+    pub youtube_playlist: String,
+    pub soundcloud_playlist: String,
+    pub video_id: String,
+    pub track_id: u64,
+    pub title: String,
+    /// RFC 3339 timestamp of when the upload finished.
+    pub time: String,
+    /// The track already existed on the Soundcloud account and was added instead of uploading the
+    /// video. Such tracks are never deleted because other playlists or the user may own them.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub reused: bool,
+}
+
+/// A video that was not transferred and will not be tried again.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Skip {
+    pub youtube_playlist: String,
+    pub video_id: String,
+    pub reason: String,
+    /// RFC 3339 timestamp of when the video was skipped.
+    pub time: String,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct Playlists {
+    #[serde(default)]
+    pub version: usize,
     pub playlists: Vec<Playlist>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct Playlist {
+    /// The id of the Youtube playlist. If `channel` is set, this is the uploads playlist of the
+    /// channel and written by the program.
+    #[serde(default)]
     pub youtube: String,
+    /// A Youtube channel id, handle or url whose uploads are transferred.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<String>,
+    /// The `channel` that `youtube` was resolved from. Written by the program.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolved_channel: Option<String>,
+    /// If empty, a Soundcloud playlist is created from the Youtube playlist and its url is stored
+    /// here.
+    #[serde(default)]
     pub soundcloud: String,
+    /// Cache of the resolved `soundcloud` url so that it does not need to be resolved on every
+    /// run. Written by the program.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolved: Option<ResolvedUrl>,
+    /// The last page of the Youtube playlist that starts before `position` so that the next run
+    /// does not need to go through all pages before it. Written by the program.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page: Option<PageStart>,
     #[serde(default)]
-    pub position: std::cell::Cell<u64>,
+    pub position: u64,
+    /// Uploaded tracks that have not been added to the Soundcloud playlist yet. Written by the
+    /// program.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unadded_tracks: Vec<u64>,
+    /// Make the Soundcloud playlist follow removals and reorderings of the Youtube playlist.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub mirror: bool,
+    /// In mirror mode, delete uploaded tracks whose video was removed from the Youtube playlist
+    /// instead of only removing them from the Soundcloud playlist.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub delete_orphans: bool,
+    /// Keep the title, description and artwork of the Soundcloud playlist the same as the Youtube
+    /// playlist.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub sync_metadata: bool,
+    /// Rules for which videos are transferred.
+    #[serde(default, skip_serializing_if = "Filters::is_empty")]
+    pub filters: Filters,
+    /// Url of the Youtube playlist thumbnail that was last uploaded as the artwork of the
+    /// Soundcloud playlist. Written by the program.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub synced_thumbnail: Option<String>,
 }
 
-impl Playlists {
-    pub fn read() -> Result<Playlists, String> {
-        let file = std::fs::OpenOptions::new()
-            .read(true)
-            .open(PLAYLISTS_FILE)
-            .map_err(|err| format!("failed to open {}: {}", PLAYLISTS_FILE, err))?;
-        serde_json::from_reader(file)
-            .map_err(|err| format!("failed to parse {}: {}", PLAYLISTS_FILE, err))
+/// A regular expression that is compiled when the config is read, so that invalid ones are
+/// reported with their position.
+#[derive(Clone, Debug)]
+pub struct Pattern(pub regex::Regex);
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Pattern) -> bool {
+        self.0.as_str() == other.0.as_str()
     }
+}
 
-    pub fn write(&self, path: &str) -> Result<(), String> {
-        let file = std::fs::OpenOptions::new()
-            .write(true)
-            .create_new(true)
-            .open(path)
-            .map_err(|err| format!("failed to open {}: {}", path, err))?;
-        serde_json::to_writer_pretty(file, self)
-            .map_err(|err| format!("failed to write to {}: {}", path, err))
+impl std::fmt::Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(self.0.as_str())
+    }
+}
+
+impl Serialize for Pattern {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Pattern, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        regex::Regex::new(&pattern).map(Pattern).map_err(|err| {
+            serde::de::Error::custom(format!("invalid regular expression {}: {}", pattern, err))
+        })
+    }
+}
+
+/// Videos that do not pass all filters are skipped.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Filters {
+    /// Regular expression the title has to match.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub include_title: Option<Pattern>,
+    /// Regular expression the title must not match.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exclude_title: Option<Pattern>,
+    /// Seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_duration: Option<u64>,
+    /// Seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_duration: Option<u64>,
+    /// `YYYY-MM-DD`. Videos published before this day are skipped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub published_after: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub exclude_live: bool,
+    /// Premieres and livestreams that have not started yet.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub exclude_upcoming: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub exclude_shorts: bool,
+}
+
+impl Filters {
+    pub fn is_empty(&self) -> bool {
+        *self == Filters::default()
+    }
+
+    /// Check that the date can be parsed. The regular expressions are checked when they are read.
+    pub fn check(&self) -> Result<(), String> {
+        self.published_after()?;
+        Ok(())
+    }
+
+    fn published_after(&self) -> Result<Option<chrono::NaiveDate>, String> {
+        self.published_after
+            .as_deref()
+            .map(|date| {
+                chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                    .map_err(|err| format!("invalid date {}: {}", date, err))
+            })
+            .transpose()
+    }
+
+    /// Whether the filters need more than the playlist item to decide.
+    pub fn needs_details(&self, video: &youtube::PlaylistItem) -> bool {
+        self.min_duration.is_some()
+            || self.max_duration.is_some()
+            || self.exclude_live
+            || self.exclude_upcoming
+            || self.exclude_shorts
+            || (self.published_after.is_some() && published(video).is_none())
+    }
+
+    /// Whether the video may be a short but the details do not say whether it is vertical.
+    pub fn needs_orientation(&self, details: &youtube::VideoDetails) -> bool {
+        self.exclude_shorts
+            && details.vertical.is_none()
+            && details.duration.is_some_and(|duration| duration <= 180)
+    }
+
+    /// The reason why the video does not pass the filters. Filters that need details are passed
+    /// if `details` is `None` or does not have the needed information.
+    pub fn rejection(
+        &self,
+        video: &youtube::PlaylistItem,
+        details: Option<&youtube::VideoDetails>,
+    ) -> Option<String> {
+        let title = &video.snippet.title;
+        if let Some(pattern) = &self.include_title {
+            if !pattern.0.is_match(title) {
+                return Some(format!("the title does not match {}", pattern));
+            }
+        }
+        if let Some(pattern) = &self.exclude_title {
+            if pattern.0.is_match(title) {
+                return Some(format!("the title matches {}", pattern));
+            }
+        }
+        let details = details.cloned().unwrap_or_default();
+        if let Some(duration) = details.duration {
+            if self.min_duration.is_some_and(|min| duration < min) {
+                return Some(format!("the video is only {} seconds long", duration));
+            }
+            if self.max_duration.is_some_and(|max| duration > max) {
+                return Some(format!("the video is {} seconds long", duration));
+            }
+        }
+        if let (Ok(Some(after)), Some(published)) = (
+            self.published_after(),
+            published(video).or(details.published),
+        ) {
+            if published < after {
+                return Some(format!("the video was published on {}", published));
+            }
+        }
+        if self.exclude_upcoming && details.upcoming {
+            return Some("the video is not available yet".to_string());
+        }
+        if self.exclude_live && details.live {
+            return Some("the video is a livestream".to_string());
+        }
+        if self.exclude_shorts && details.is_short() {
+            return Some("the video is a short".to_string());
+        }
+        None
+    }
+}
+
+/// The day the video was published according to the api.
+fn published(video: &youtube::PlaylistItem) -> Option<chrono::NaiveDate> {
+    chrono::DateTime::parse_from_rfc3339(&video.contentDetails.videoPublishedAt)
+        .ok()
+        .map(|published| published.date_naive())
+}
+
+/// A page token of a Youtube playlist and the position of the first video on that page.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PageStart {
+    pub youtube: String,
+    pub token: String,
+    pub position: u64,
+}
+
+/// A Soundcloud playlist url and the api url it resolved to.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ResolvedUrl {
+    pub url: String,
+    pub api_url: String,
+}
+
+impl Playlist {
+    /// Replace a playlist url in `youtube` with the playlist id and check that the source is
+    /// valid.
+    pub fn normalize(&mut self) -> Result<(), String> {
+        match &self.channel {
+            Some(channel) if youtube::parse_channel(channel).is_empty() => {
+                return Err(format!("{} is not a valid Youtube channel", channel))
+            }
+            None if self.youtube.is_empty() => {
+                return Err("neither a youtube playlist nor a channel is set".to_string())
+            }
+            _ => {}
+        }
+        if !self.youtube.is_empty() {
+            self.youtube = youtube::parse_playlist_id(&self.youtube)?;
+        }
+        self.filters.check()
+    }
+}
+
+impl Playlists {
+    /// Playlist urls are replaced with their ids and written back.
+    pub fn read() -> Result<Playlists, String> {
+        let path = playlists_path();
+        let mut playlists: Playlists = read_recovering(&path, PLAYLISTS_MIGRATIONS, false)?;
+        let mut changed = false;
+        for (index, playlist) in playlists.playlists.iter_mut().enumerate() {
+            let youtube = playlist.youtube.clone();
+            playlist
+                .normalize()
+                .map_err(|err| format!("playlist {} in {}: {}", index + 1, path, err))?;
+            changed |= playlist.youtube != youtube;
+        }
+        if changed {
+            println!("Replacing Youtube playlist urls with ids in {}.", path);
+            playlists.write_safe()?;
+        }
+        Ok(playlists)
     }
 
     pub fn write_safe(&self) -> Result<(), String> {
-        std::fs::rename(PLAYLISTS_FILE, PLAYLISTS_BACKUP_FILE).map_err(|err| {
-            format!(
-                "failed to rename {} to {}: {}",
-                PLAYLISTS_FILE, PLAYLISTS_BACKUP_FILE, err
-            )
-        })?;
-        self.write(PLAYLISTS_FILE)
+        write_safe(self, &playlists_path(), false)
     }
 }
 
 impl Config {
+    /// The Soundcloud genre for a Youtube category, looked up by its id or its name. Only listed
+    /// categories get a genre.
+    pub fn genre(&self, category_id: &str) -> Option<String> {
+        self.genres
+            .get(category_id)
+            .or_else(|| youtube::category_name(category_id).and_then(|name| self.genres.get(name)))
+            .filter(|genre| !genre.is_empty())
+            .cloned()
+    }
+
     pub fn read() -> Result<Config, String> {
-        let path = CONFIG_FILE;
+        let path = config_path();
+        // The upgrade removes it from the file.
+        let legacy_access_token = legacy_access_token(&path);
+        let mut config: Config = read_recovering(&path, CONFIG_MIGRATIONS, false)?;
+        config.legacy_access_token = legacy_access_token;
+        config.complete()?;
+        Ok(config)
+    }
+
+    /// Apply the credentials from the secrets file and the environment and check that none are
+    /// missing.
+    pub fn complete(&mut self) -> Result<(), String> {
+        if let Some(secrets_path) = self.secrets_path() {
+            self.apply(Credentials::read(&secrets_path)?);
+        }
+        self.apply(Credentials::from_env());
+        self.check()
+    }
+
+    pub fn batch_size(&self) -> usize {
+        self.batch_size.unwrap_or(DEFAULT_BATCH_SIZE).max(1)
+    }
+
+    /// The secrets file if one is configured or the default one exists.
+    pub fn secrets_path(&self) -> Option<String> {
+        match &self.secrets_file {
+            Some(path) => Some(path.clone()),
+            None => {
+                Some(format::find(SECRETS_FILE)).filter(|path| std::path::Path::new(path).exists())
+            }
+        }
+    }
+
+    fn apply(&mut self, credentials: Credentials) {
+        let fields = vec![
+            (
+                &mut self.soundcloud_client_id,
+                credentials.soundcloud_client_id,
+            ),
+            (
+                &mut self.soundcloud_client_secret,
+                credentials.soundcloud_client_secret,
+            ),
+            (
+                &mut self.soundcloud_username,
+                credentials.soundcloud_username,
+            ),
+            (
+                &mut self.soundcloud_password,
+                credentials.soundcloud_password,
+            ),
+            (&mut self.youtube_api_key, credentials.youtube_api_key),
+        ];
+        for (field, value) in fields {
+            if let Some(value) = value {
+                *field = value;
+            }
+        }
+    }
+
+    fn check(&self) -> Result<(), String> {
+        let fields = [
+            ("soundcloud_client_id", &self.soundcloud_client_id),
+            ("soundcloud_client_secret", &self.soundcloud_client_secret),
+            ("soundcloud_username", &self.soundcloud_username),
+            ("soundcloud_password", &self.soundcloud_password),
+            ("youtube_api_key", &self.youtube_api_key),
+        ];
+        let missing: Vec<&str> = fields
+            .iter()
+            .filter(|(name, value)| {
+                value.is_empty()
+                    && !(*name == "youtube_api_key"
+                        && self.youtube_backend == YoutubeBackend::YoutubeDl)
+            })
+            .map(|(name, _)| *name)
+            .collect();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "missing credentials: {}. Set them in {}, the secrets file or the environment.",
+                missing.join(", "),
+                config_path()
+            ))
+        }
+    }
+}
+
+impl Credentials {
+    pub fn read(path: &str) -> Result<Credentials, String> {
         let file = std::fs::OpenOptions::new()
             .read(true)
             .open(path)
             .map_err(|err| format!("failed to open {}: {}", path, err))?;
-        serde_json::from_reader(file).map_err(|err| format!("failed to parse {}: {}", path, err))
+        check_permissions(&file, path)?;
+        let text = std::io::read_to_string(file)
+            .map_err(|err| format!("failed to read {}: {}", path, err))?;
+        Format::of(path)
+            .deserialize(&text)
+            .map_err(|err| format!("failed to parse {}: {}", path, err))
     }
 
-    pub fn write(&self, path: &str) -> Result<(), String> {
-        let file = std::fs::OpenOptions::new()
-            .write(true)
-            .create_new(true)
-            .open(path)
-            .map_err(|err| format!("failed to open {}: {}", path, err))?;
-        serde_json::to_writer_pretty(file, self)
-            .map_err(|err| format!("failed to write to {}: {}", path, err))
+    pub fn from_env() -> Credentials {
+        let var = |name: &str| std::env::var(name.to_uppercase()).ok();
+        Credentials {
+            soundcloud_client_id: var("soundcloud_client_id"),
+            soundcloud_client_secret: var("soundcloud_client_secret"),
+            soundcloud_username: var("soundcloud_username"),
+            soundcloud_password: var("soundcloud_password"),
+            youtube_api_key: var("youtube_api_key"),
+        }
+    }
+}
+
+impl State {
+    /// Returns the default state if the state file does not exist yet.
+    pub fn read() -> Result<State, String> {
+        if !std::path::Path::new(STATE_FILE).exists() && backups(STATE_FILE)?.is_empty() {
+            return Ok(State {
+                version: STATE_MIGRATIONS.len(),
+                ..Default::default()
+            });
+        }
+        read_recovering(STATE_FILE, STATE_MIGRATIONS, true)
     }
 
     pub fn write_safe(&self) -> Result<(), String> {
-        std::fs::rename(CONFIG_FILE, CONFIG_BACKUP_FILE).map_err(|err| {
-            format!(
-                "failed to rename {} to {}: {}",
-                CONFIG_FILE, CONFIG_BACKUP_FILE, err
-            )
-        })?;
-        self.write(CONFIG_FILE)
+        write_safe(self, STATE_FILE, true)
+    }
+
+    /// Replace the file without a backup. For frequent updates like the quota usage, which would
+    /// otherwise push the backups from before the run out.
+    pub fn write_without_backup(&self) -> Result<(), String> {
+        write_replacing(self, STATE_FILE, true)
+    }
+}
+
+/// Bring `value` to the current version. Returns whether anything changed.
+pub fn migrate(path: &str, value: &mut Value, migrations: &[Migration]) -> Result<bool, String> {
+    let object = value
+        .as_object_mut()
+        .ok_or_else(|| format!("{} does not contain a json object", path))?;
+    let version = match object.get("version") {
+        None => 0,
+        Some(version) => version
+            .as_u64()
+            .ok_or_else(|| format!("the version in {} is not a positive integer", path))?
+            as usize,
+    };
+    if version > migrations.len() {
+        return Err(format!(
+            "{} has version {} but this program only supports up to version {}. \
+             Update the program.",
+            path,
+            version,
+            migrations.len()
+        ));
+    }
+    for migration in migrations[version..].iter() {
+        migration(object)?;
+    }
+    object.insert("version".to_string(), migrations.len().into());
+    Ok(version < migrations.len())
+}
+
+fn unversioned_to_v1(_: &mut Map<String, Value>) -> Result<(), String> {
+    Ok(())
+}
+
+/// The access token moved to the store, see `legacy_access_token`. Migrations only change the
+/// file they upgrade so that they can be checked without side effects.
+fn config_v0_to_v1(config: &mut Map<String, Value>) -> Result<(), String> {
+    config.remove("soundcloud_access_token");
+    Ok(())
+}
+
+/// The access token in a config file of version 0.
+fn legacy_access_token(path: &str) -> Option<String> {
+    let text = std::fs::read_to_string(path).ok()?;
+    let mut value: Value = Format::of(path).deserialize(&text).ok()?;
+    let config = value.as_object_mut()?;
+    if config.get("version").and_then(Value::as_u64).unwrap_or(0) != 0 {
+        return None;
+    }
+    match config.remove("soundcloud_access_token") {
+        Some(Value::String(access_token)) => Some(access_token),
+        _ => None,
+    }
+}
+
+/// Parse the file. If it has an older version, the upgraded json is returned too.
+fn read_file<T: DeserializeOwned>(
+    path: &str,
+    migrations: &[Migration],
+) -> Result<(T, Option<Value>), String> {
+    let text =
+        std::fs::read_to_string(path).map_err(|err| format!("failed to open {}: {}", path, err))?;
+    let format = Format::of(path);
+    let parse_error = |err| format!("failed to parse {}: {}", path, err);
+    let mut value: Value = format.deserialize(&text).map_err(parse_error)?;
+    if migrate(path, &mut value, migrations)? {
+        let result = serde_json::from_value(value.clone())
+            .map_err(|err| err.to_string())
+            .map_err(parse_error)?;
+        Ok((result, Some(value)))
+    } else {
+        // Parse the text again for errors with line numbers.
+        Ok((format.deserialize(&text).map_err(parse_error)?, None))
+    }
+}
+
+/// Read the file and if it is missing or cannot be parsed, restore the newest backup that can.
+/// Files with an older version are upgraded in place.
+fn read_recovering<T: DeserializeOwned>(
+    path: &str,
+    migrations: &[Migration],
+    private: bool,
+) -> Result<T, String> {
+    let (result, upgraded) = match read_file(path, migrations) {
+        Ok(result) => result,
+        Err(err) => {
+            let mut recovered = None;
+            for backup in backups(path)?.iter().rev() {
+                if let Ok((result, upgraded)) = read_file(backup, migrations) {
+                    println!("Error: {}\nRestoring the backup {}.", err, backup);
+                    let contents = std::fs::read(backup)
+                        .map_err(|err| format!("failed to read {}: {}", backup, err))?;
+                    util::write_atomic(path, &contents, private)?;
+                    recovered = Some((result, upgraded));
+                    break;
+                }
+            }
+            recovered.ok_or(err)?
+        }
+    };
+    if let Some(upgraded) = upgraded {
+        println!(
+            "Upgrading {} to version {}. The old version is kept in the {} folder.",
+            path,
+            migrations.len(),
+            BACKUP_DIRECTORY
+        );
+        write_safe(&upgraded, path, private)?;
+    }
+    Ok(result)
+}
+
+/// Back up the current file and then atomically replace it. If `private` is set the file is only
+/// accessible by the current user.
+fn write_safe<T: Serialize>(value: &T, path: &str, private: bool) -> Result<(), String> {
+    backup(path)?;
+    write_replacing(value, path, private)
+}
+
+/// Atomically replace the file, keeping the comments of formats that support them.
+fn write_replacing<T: Serialize>(value: &T, path: &str, private: bool) -> Result<(), String> {
+    let value = serde_json::to_value(value)
+        .map_err(|err| format!("failed to serialize {}: {}", path, err))?;
+    let previous = std::fs::read_to_string(path).ok();
+    let contents = Format::of(path)
+        .serialize(&value, previous.as_deref())
+        .map_err(|err| format!("failed to serialize {}: {}", path, err))?;
+    util::write_atomic(path, contents.as_bytes(), private)
+}
+
+/// Returns (prefix, suffix) of the backup file names of `path`.
+fn backup_name_parts(path: &str) -> (String, String) {
+    let path = std::path::Path::new(path);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path.extension().unwrap_or_default().to_string_lossy();
+    (format!("{}-", stem), format!(".{}", extension))
+}
+
+/// The backup folder next to `path`.
+fn backup_directory(path: &str) -> std::path::PathBuf {
+    std::path::Path::new(path)
+        .parent()
+        .unwrap_or_else(|| std::path::Path::new(""))
+        .join(BACKUP_DIRECTORY)
+}
+
+/// The backups of `path` from oldest to newest.
+fn backups(path: &str) -> Result<Vec<String>, String> {
+    let (prefix, suffix) = backup_name_parts(path);
+    let directory = backup_directory(path);
+    let entries = match std::fs::read_dir(&directory) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => {
+            return Err(format!(
+                "failed to read {}: {}",
+                util::path_to_str(&directory),
+                err
+            ))
+        }
+    };
+    let mut backups: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .filter(|name| name.starts_with(&prefix) && name.ends_with(&suffix))
+        .map(|name| util::path_to_str(directory.join(name)))
+        .collect();
+    // The timestamp format sorts lexicographically.
+    backups.sort();
+    Ok(backups)
+}
+
+/// Copy the file to a new timestamped backup and remove the oldest backups so that at most
+/// `BACKUP_COUNT` remain.
+fn backup(path: &str) -> Result<(), String> {
+    if !std::path::Path::new(path).exists() {
+        return Ok(());
+    }
+    let directory = backup_directory(path);
+    std::fs::create_dir_all(&directory).map_err(|err| {
+        format!(
+            "failed to create {}: {}",
+            util::path_to_str(&directory),
+            err
+        )
+    })?;
+    let (prefix, suffix) = backup_name_parts(path);
+    let timestamp = chrono::Utc::now().format("%Y%m%dT%H%M%S%.6f");
+    let backup = util::path_to_str(directory.join(format!("{}{}{}", prefix, timestamp, suffix)));
+    std::fs::copy(path, &backup)
+        .map_err(|err| format!("failed to copy {} to {}: {}", path, backup, err))?;
+    let backups = backups(path)?;
+    for old in backups
+        .iter()
+        .take(backups.len().saturating_sub(BACKUP_COUNT))
+    {
+        if let Err(err) = std::fs::remove_file(old) {
+            println!("Error: failed to remove old backup {}: {}", old, err);
+        }
+    }
+    Ok(())
+}
+
+/// Refuse to read secrets from a file that other users can access.
+#[cfg(unix)]
+fn check_permissions(file: &std::fs::File, path: &str) -> Result<(), String> {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = file
+        .metadata()
+        .map_err(|err| format!("failed to get metadata of {}: {}", path, err))?
+        .permissions()
+        .mode();
+    if mode & 0o077 != 0 {
+        return Err(format!(
+            "{} is accessible by other users (mode {:o}). Restrict it with chmod 600.",
+            path,
+            mode & 0o777
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_permissions(_file: &std::fs::File, _path: &str) -> Result<(), String> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object(json: &str) -> Value {
+        serde_json::from_str(json).unwrap()
+    }
+
+    /// An empty folder for the files of one test.
+    fn directory(name: &str) -> std::path::PathBuf {
+        let directory = std::env::temp_dir().join(format!(
+            "youtube_to_soundcloud-{}-{}",
+            std::process::id(),
+            name
+        ));
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    fn playlists(position: u64) -> Playlists {
+        serde_json::from_value(serde_json::json!({
+            "version": PLAYLISTS_MIGRATIONS.len(),
+            "playlists": [{"youtube": "PLabc", "soundcloud": "url", "position": position}]
+        }))
+        .unwrap()
+    }
+
+    fn read_position(path: &str) -> Result<u64, String> {
+        read_recovering::<Playlists>(path, PLAYLISTS_MIGRATIONS, false)
+            .map(|playlists| playlists.playlists[0].position)
+    }
+
+    #[test]
+    fn corrupt_file_is_restored_from_the_newest_valid_backup() {
+        let directory = directory("corrupt");
+        let path = util::path_to_str(directory.join("playlists.json"));
+        write_safe(&playlists(1), &path, false).unwrap();
+        write_safe(&playlists(2), &path, false).unwrap();
+        // A newer backup that cannot be parsed is passed over.
+        std::fs::write(
+            backup_directory(&path).join("playlists-99999999T000000.000000.json"),
+            "{",
+        )
+        .unwrap();
+        std::fs::write(&path, "{\"playlists\": [").unwrap();
+        assert_eq!(read_position(&path), Ok(1));
+        assert_eq!(
+            read_file::<Playlists>(&path, PLAYLISTS_MIGRATIONS)
+                .unwrap()
+                .0
+                .playlists[0]
+                .position,
+            1
+        );
+    }
+
+    #[test]
+    fn missing_file_is_restored_from_a_backup() {
+        let directory = directory("missing");
+        let path = util::path_to_str(directory.join("playlists.json"));
+        write_safe(&playlists(1), &path, false).unwrap();
+        write_safe(&playlists(2), &path, false).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read_position(&path), Ok(1));
+        assert!(std::path::Path::new(&path).exists());
+    }
+
+    #[test]
+    fn missing_file_without_backups_is_an_error() {
+        let directory = directory("no-backups");
+        let path = util::path_to_str(directory.join("playlists.json"));
+        assert!(read_position(&path).is_err());
+    }
+
+    #[test]
+    fn only_the_newest_backups_are_kept() {
+        let directory = directory("rotation");
+        let path = util::path_to_str(directory.join("playlists.json"));
+        for position in 0..BACKUP_COUNT as u64 + 3 {
+            write_safe(&playlists(position), &path, false).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+        let backups = backups(&path).unwrap();
+        assert_eq!(backups.len(), BACKUP_COUNT);
+        let newest: Playlists =
+            serde_json::from_str(&std::fs::read_to_string(backups.last().unwrap()).unwrap())
+                .unwrap();
+        assert_eq!(newest.playlists[0].position, BACKUP_COUNT as u64 + 1);
+    }
+
+    #[test]
+    fn replacing_without_backup_keeps_the_backups() {
+        let directory = directory("no-backup");
+        let path = util::path_to_str(directory.join("playlists.json"));
+        write_safe(&playlists(1), &path, false).unwrap();
+        write_safe(&playlists(2), &path, false).unwrap();
+        write_replacing(&playlists(3), &path, false).unwrap();
+        assert_eq!(backups(&path).unwrap().len(), 1);
+        assert_eq!(read_position(&path), Ok(3));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn upgrade_keeps_the_permissions_of_the_file() {
+        use std::os::unix::fs::PermissionsExt;
+        let directory = directory("upgrade");
+        let path = util::path_to_str(directory.join("config.json"));
+        std::fs::write(&path, r#"{"soundcloud_password": "secret"}"#).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+        read_recovering::<Config>(&path, CONFIG_MIGRATIONS, false).unwrap();
+        let metadata = std::fs::metadata(&path).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        assert!(std::fs::read_to_string(&path)
+            .unwrap()
+            .contains("\"version\": 1"));
+    }
+
+    #[test]
+    fn config_v0_moves_access_token_out() {
+        let mut value = object(r#"{"soundcloud_access_token": "token", "youtube_api_key": "key"}"#);
+        assert!(migrate("config.json", &mut value, CONFIG_MIGRATIONS).unwrap());
+        assert_eq!(value, object(r#"{"youtube_api_key": "key", "version": 1}"#));
+    }
+
+    #[test]
+    fn unversioned_file_is_upgraded() {
+        let mut value = object(r#"{"playlists": []}"#);
+        assert!(migrate("playlists.json", &mut value, PLAYLISTS_MIGRATIONS).unwrap());
+        assert_eq!(value["version"], 1);
+    }
+
+    #[test]
+    fn current_version_is_unchanged() {
+        let mut value = object(r#"{"version": 1, "playlists": []}"#);
+        assert!(!migrate("playlists.json", &mut value, PLAYLISTS_MIGRATIONS).unwrap());
+        assert_eq!(value, object(r#"{"version": 1, "playlists": []}"#));
+    }
+
+    #[test]
+    fn newer_and_invalid_versions_are_rejected() {
+        let mut newer = object(r#"{"version": 2}"#);
+        assert!(migrate("config.json", &mut newer, CONFIG_MIGRATIONS).is_err());
+        let mut invalid = object(r#"{"version": "1"}"#);
+        assert!(migrate("config.json", &mut invalid, CONFIG_MIGRATIONS).is_err());
+        let mut array = object("[]");
+        assert!(migrate("config.json", &mut array, CONFIG_MIGRATIONS).is_err());
+    }
+
+    #[test]
+    fn upgraded_config_parses() {
+        let mut value = object(r#"{"soundcloud_access_token": "token", "store": "sqlite"}"#);
+        migrate("config.json", &mut value, CONFIG_MIGRATIONS).unwrap();
+        let config: Config = serde_json::from_value(value).unwrap();
+        assert_eq!(config.store, StoreKind::Sqlite);
+        assert_eq!(config.version, CONFIG_MIGRATIONS.len());
+    }
+
+    fn video(title: &str, published: &str) -> youtube::PlaylistItem {
+        serde_json::from_value(serde_json::json!({
+            "contentDetails": {"videoId": "id", "videoPublishedAt": published},
+            "snippet": {
+                "title": title,
+                "description": "",
+                "position": 0,
+                "publishedAt": published,
+                "thumbnails": {"default": {"url": ""}}
+            }
+        }))
+        .unwrap()
+    }
+
+    fn filters(json: &str) -> Filters {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn titles_are_filtered() {
+        let filters = filters(r#"{"include_title": "(?i)live", "exclude_title": "teaser"}"#);
+        assert_eq!(filters.rejection(&video("Live set", ""), None), None);
+        assert_eq!(
+            filters.rejection(&video("Studio set", ""), None),
+            Some("the title does not match (?i)live".to_string())
+        );
+        assert_eq!(
+            filters.rejection(&video("Live teaser", ""), None),
+            Some("the title matches teaser".to_string())
+        );
+    }
+
+    #[test]
+    fn invalid_regular_expressions_are_rejected_when_read() {
+        assert!(serde_json::from_str::<Filters>(r#"{"include_title": "(a"}"#).is_err());
+    }
+
+    #[test]
+    fn details_are_filtered() {
+        let filters = filters(
+            r#"{"min_duration": 60, "max_duration": 600, "published_after": "2020-01-01",
+                "exclude_live": true, "exclude_upcoming": true, "exclude_shorts": true}"#,
+        );
+        let video = video("title", "2021-05-01T00:00:00Z");
+        let details = |details: youtube::VideoDetails| filters.rejection(&video, Some(&details));
+        let normal = youtube::VideoDetails {
+            duration: Some(300),
+            vertical: Some(false),
+            ..Default::default()
+        };
+        assert_eq!(details(normal.clone()), None);
+        assert_eq!(
+            details(youtube::VideoDetails {
+                duration: Some(30),
+                ..normal.clone()
+            }),
+            Some("the video is only 30 seconds long".to_string())
+        );
+        assert_eq!(
+            details(youtube::VideoDetails {
+                duration: Some(601),
+                ..normal.clone()
+            }),
+            Some("the video is 601 seconds long".to_string())
+        );
+        assert_eq!(
+            details(youtube::VideoDetails {
+                live: true,
+                ..normal.clone()
+            }),
+            Some("the video is a livestream".to_string())
+        );
+        assert_eq!(
+            details(youtube::VideoDetails {
+                upcoming: true,
+                ..normal.clone()
+            }),
+            Some("the video is not available yet".to_string())
+        );
+        assert_eq!(
+            details(youtube::VideoDetails {
+                duration: Some(120),
+                vertical: Some(true),
+                ..normal
+            }),
+            Some("the video is a short".to_string())
+        );
+        assert_eq!(
+            filters.rejection(&self::video("title", "2019-12-31T23:00:00Z"), None),
+            Some("the video was published on 2019-12-31".to_string())
+        );
+        // Filters that need details pass without them.
+        assert_eq!(filters.rejection(&video, None), None);
+    }
+
+    #[test]
+    fn genres_are_only_set_for_listed_categories() {
+        let config: Config = serde_json::from_str(
+            r#"{"genres": {"10": "Electronic", "Gaming": "Soundtrack", "22": ""}}"#,
+        )
+        .unwrap();
+        assert_eq!(config.genre("10"), Some("Electronic".to_string()));
+        assert_eq!(config.genre("20"), Some("Soundtrack".to_string()));
+        assert_eq!(config.genre("22"), None);
+        assert_eq!(config.genre("24"), None);
     }
 }
@@ -0,0 +1,215 @@
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+
+/// File formats for configuration files. The format is determined by the file extension.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Json,
+    Toml,
+    Yaml,
+}
+
+const EXTENSIONS: &[(&str, Format)] = &[
+    ("json", Format::Json),
+    ("toml", Format::Toml),
+    ("yaml", Format::Yaml),
+    ("yml", Format::Yaml),
+];
+
+impl Format {
+    /// Files with an unknown extension are treated as json.
+    pub fn of(path: &str) -> Format {
+        let extension = std::path::Path::new(path)
+            .extension()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_lowercase();
+        EXTENSIONS
+            .iter()
+            .find(|(name, _)| *name == extension)
+            .map(|(_, format)| *format)
+            .unwrap_or(Format::Json)
+    }
+
+    /// The errors contain the line and column.
+    pub fn deserialize<T: DeserializeOwned>(self, text: &str) -> Result<T, String> {
+        match self {
+            Format::Json => serde_json::from_str(text).map_err(|err| err.to_string()),
+            Format::Toml => toml::from_str(text).map_err(|err| err.to_string()),
+            Format::Yaml => serde_yaml::from_str(text).map_err(|err| err.to_string()),
+        }
+    }
+
+    /// For toml, `previous` is the current content of the file. Its comments and formatting are
+    /// kept for everything that did not change.
+    pub fn serialize(self, value: &Value, previous: Option<&str>) -> Result<String, String> {
+        match self {
+            Format::Json => serde_json::to_string_pretty(value).map_err(|err| err.to_string()),
+            Format::Yaml => serde_yaml::to_string(value).map_err(|err| err.to_string()),
+            Format::Toml => {
+                let value = without_nulls(value);
+                let mut document = match previous.map(str::parse::<toml_edit::DocumentMut>) {
+                    Some(Ok(document)) => document,
+                    _ => return toml::to_string_pretty(&value).map_err(|err| err.to_string()),
+                };
+                match &value {
+                    Value::Object(map) => merge_table(document.as_table_mut(), map)?,
+                    _ => return Err("the top level value is not a table".to_string()),
+                }
+                Ok(document.to_string())
+            }
+        }
+    }
+}
+
+/// The path of the file with the name of `default` that exists in any of the supported formats.
+/// If there is none, `default` is returned.
+pub fn find(default: &str) -> String {
+    let path = std::path::Path::new(default);
+    EXTENSIONS
+        .iter()
+        .map(|(extension, _)| path.with_extension(extension))
+        .find(|candidate| candidate.exists())
+        .map(|candidate| candidate.to_string_lossy().into_owned())
+        .unwrap_or_else(|| default.to_string())
+}
+
+/// Toml has no null so fields that are not set are left out.
+fn without_nulls(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(key, value)| (key.clone(), without_nulls(value)))
+                .collect(),
+        ),
+        Value::Array(values) => Value::Array(values.iter().map(without_nulls).collect()),
+        other => other.clone(),
+    }
+}
+
+fn merge_table(table: &mut toml_edit::Table, map: &Map<String, Value>) -> Result<(), String> {
+    let removed: Vec<String> = table
+        .iter()
+        .map(|(key, _)| key.to_string())
+        .filter(|key| !map.contains_key(key))
+        .collect();
+    for key in removed {
+        table.remove(&key);
+    }
+    for (key, value) in map {
+        match table.get_mut(key) {
+            Some(item) => merge_item(item, value)?,
+            None => {
+                table.insert(key, to_item(value)?);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn merge_item(item: &mut toml_edit::Item, value: &Value) -> Result<(), String> {
+    match (&mut *item, value) {
+        (toml_edit::Item::Table(table), Value::Object(map)) => merge_table(table, map),
+        (toml_edit::Item::ArrayOfTables(array), Value::Array(values))
+            if values.iter().all(Value::is_object) =>
+        {
+            while array.len() > values.len() {
+                array.remove(array.len() - 1);
+            }
+            for (index, value) in values.iter().enumerate() {
+                let map = value.as_object().unwrap();
+                match array.get_mut(index) {
+                    Some(table) => merge_table(table, map)?,
+                    None => match to_item(value)? {
+                        toml_edit::Item::Table(table) => array.push(table),
+                        _ => return Err("failed to convert a table".to_string()),
+                    },
+                }
+            }
+            Ok(())
+        }
+        (item, value) => {
+            if to_json(item).as_ref() == Some(value) {
+                return Ok(());
+            }
+            let mut new = to_item(value)?;
+            if let (Some(old), Some(new)) = (item.as_value(), new.as_value_mut()) {
+                *new.decor_mut() = old.decor().clone();
+            }
+            *item = new;
+            Ok(())
+        }
+    }
+}
+
+/// Convert by going through the text representation so that the toml crate decides how to
+/// represent the value.
+fn to_item(value: &Value) -> Result<toml_edit::Item, String> {
+    let mut wrapper = Map::new();
+    wrapper.insert("value".to_string(), value.clone());
+    let text = toml::to_string(&wrapper).map_err(|err| err.to_string())?;
+    let mut document = text
+        .parse::<toml_edit::DocumentMut>()
+        .map_err(|err| err.to_string())?;
+    Ok(document.remove("value").unwrap_or_default())
+}
+
+fn to_json(item: &toml_edit::Item) -> Option<Value> {
+    let mut document = toml_edit::DocumentMut::new();
+    document.insert("value", item.clone());
+    toml::from_str::<Map<String, Value>>(&document.to_string())
+        .ok()?
+        .remove("value")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merged(previous: &str, json: &str) -> String {
+        let value: Value = serde_json::from_str(json).unwrap();
+        Format::Toml.serialize(&value, Some(previous)).unwrap()
+    }
+
+    #[test]
+    fn comments_of_unchanged_values_are_kept() {
+        let previous = "# Mappings\nversion = 1\n\n[[playlists]]\n# Music\nyoutube = \"PLabc\" # main\nposition = 3\n";
+        let result = merged(
+            previous,
+            r#"{"version": 1, "playlists": [{"youtube": "PLabc", "position": 4}]}"#,
+        );
+        assert_eq!(
+            result,
+            "# Mappings\nversion = 1\n\n[[playlists]]\n# Music\nyoutube = \"PLabc\" # main\nposition = 4\n"
+        );
+    }
+
+    #[test]
+    fn removed_keys_and_tables_are_dropped() {
+        let previous = "version = 1\nold = true\n\n[[playlists]]\nyoutube = \"a\"\n\n[[playlists]]\nyoutube = \"b\"\n";
+        let result = merged(
+            previous,
+            r#"{"version": 1, "playlists": [{"youtube": "a"}]}"#,
+        );
+        assert_eq!(result, "version = 1\n\n[[playlists]]\nyoutube = \"a\"\n");
+    }
+
+    #[test]
+    fn new_keys_and_tables_are_added() {
+        let previous = "version = 1\n\n[[playlists]]\nyoutube = \"a\"\n";
+        let result = merged(
+            previous,
+            r#"{"version": 1, "playlists": [{"youtube": "a", "position": 1}, {"youtube": "b", "resolved": null}]}"#,
+        );
+        let value: Value = toml::from_str(&result).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "version": 1,
+                "playlists": [{"youtube": "a", "position": 1}, {"youtube": "b"}]
+            })
+        );
+        assert!(result.starts_with("version = 1\n\n[[playlists]]\nyoutube = \"a\"\n"));
+    }
+}
@@ -0,0 +1,64 @@
+use std::io::{Seek, Write};
+
+pub const LOCK_FILE: &str = "youtube_to_soundcloud.lock";
+
+/// Advisory lock that prevents multiple instances from working on the same files at the same time.
+/// The lock is held on the open lock file and released by the operating system when the owner
+/// exits, so a crashed instance cannot leave a stale lock behind. The lock file contains the
+/// process id of the owner for information and is kept, because removing it would let another
+/// instance lock a new file while a third one still holds the old one.
+#[derive(Debug)]
+pub struct Lock {
+    _file: std::fs::File,
+}
+
+impl Lock {
+    /// Returns `None` if another running instance holds the lock.
+    pub fn try_acquire(path: &str) -> Result<Option<Lock>, String> {
+        let mut file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .map_err(|err| format!("failed to open {}: {}", path, err))?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(std::fs::TryLockError::WouldBlock) => return Ok(None),
+            Err(std::fs::TryLockError::Error(err)) => {
+                return Err(format!("failed to lock {}: {}", path, err))
+            }
+        }
+        file.set_len(0)
+            .and_then(|_| file.rewind())
+            .and_then(|_| write!(file, "{}", std::process::id()))
+            .and_then(|_| file.sync_all())
+            .map_err(|err| format!("failed to write to {}: {}", path, err))?;
+        Ok(Some(Lock { _file: file }))
+    }
+
+    /// If `wait` is set, block until the lock becomes available instead of failing.
+    pub fn acquire(path: &str, wait: bool) -> Result<Lock, String> {
+        let mut waiting = false;
+        loop {
+            if let Some(lock) = Lock::try_acquire(path)? {
+                return Ok(lock);
+            }
+            if !wait {
+                let owner = std::fs::read_to_string(path)
+                    .map(|owner| format!(" by process {}", owner.trim()))
+                    .unwrap_or_default();
+                return Err(format!(
+                    "another instance is already running (lock file {} is held{}). \
+                     Use --wait to wait for it to finish.",
+                    path, owner
+                ));
+            }
+            if !waiting {
+                println!("Another instance is running. Waiting for it to finish.");
+                waiting = true;
+            }
+            std::thread::sleep(std::time::Duration::from_secs(10));
+        }
+    }
+}
@@ -2,9 +2,16 @@ use chrono::Datelike;
 use reqwest::blocking::Client;
 use std::str::FromStr;
 
+mod cache;
 mod config;
+mod format;
+mod lock;
 mod soundcloud;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod store;
 mod util;
+mod validate;
 mod youtube;
 
 fn default_backoff() -> backoff::ExponentialBackoff {
@@ -15,16 +22,44 @@ fn default_backoff() -> backoff::ExponentialBackoff {
     }
 }
 
+/// How often tracks that Soundcloud dropped from a playlist update are added again.
+const MEMBERSHIP_RETRIES: u32 = 2;
+/// Soundcloud drops tracks that are still processing, so wait before adding them again.
+const MEMBERSHIP_RETRY_DELAY: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Lifetime of application access tokens if Soundcloud does not say when they expire.
+const APP_TOKEN_LIFETIME: i64 = 60 * 60;
+
+/// How often a video is uploaded again when Soundcloud fails to process it.
+const UPLOAD_ATTEMPTS: u32 = 3;
+const PROCESSING_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(15);
+/// Uploads that are still processing after this long are treated as failed.
+const PROCESSING_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
 struct App {
+    _lock: lock::Lock,
     config: config::Config,
-    playlists: config::Playlists,
+    store: Box<dyn store::Store>,
     client: Client,
     access_token: String,
+    /// Requested when it is first needed and renewed when it expires.
+    app_token: Option<config::AppToken>,
+    youtube_cache: cache::ResponseCache,
+    /// Youtube api quota units used today, including this run.
+    youtube_quota: config::QuotaUsage,
+    /// Youtube api quota units used by this run.
+    youtube_units: u64,
+    /// Playlists listed with youtube-dl in this run by id, so that each is only listed once.
+    youtube_dl_listings:
+        std::collections::HashMap<String, (youtube::Playlist, Vec<youtube::PlaylistItem>)>,
 }
 
 impl App {
-    fn new() -> Result<App, String> {
-        let mut config = config::Config::read()?;
+    fn new(wait: bool) -> Result<App, String> {
+        let lock = lock::Lock::acquire(lock::LOCK_FILE, wait)?;
+        let config = config::Config::read()?;
+        let mut store = store::open(&config)?;
+        let stored_access_token = store.access_token();
         // Currently soundclouds playlisturl to api url needs redirects to be disabled for resolve to
         // work correctly.
         let client = reqwest::blocking::ClientBuilder::new()
@@ -36,14 +71,13 @@ impl App {
         // client.proxy(reqwest::Proxy::https("http://localhost:8888").unwrap());
 
         println!("Checking validity of existing Soundcloud access token.");
-        let need_new_token = match config.soundcloud_access_token {
+        let need_new_token = match stored_access_token {
             Some(ref access_token) => {
                 let mut op = || {
-                    soundcloud::is_token_valid(&config.soundcloud_client_id, access_token, &client)
-                        .map_err(|err| {
-                            println!("Error: {}\nRetrying...", err);
-                            backoff::Error::Transient(err)
-                        })
+                    soundcloud::is_token_valid(access_token, &client).map_err(|err| {
+                        println!("Error: {}\nRetrying...", err);
+                        backoff::Error::Transient(err)
+                    })
                 };
                 !backoff::Operation::retry(&mut op, &mut default_backoff()).unwrap()
             }
@@ -72,21 +106,27 @@ impl App {
                 None => return Err("The Soundcloud account details are not valid.".to_string()),
             }
         } else {
-            access_token = config.soundcloud_access_token.clone().unwrap()
+            access_token = stored_access_token.unwrap()
         };
         if need_new_token {
-            config.soundcloud_access_token = Some(access_token.clone());
-            config.write_safe()?;
+            store.set_access_token(&access_token)?;
         };
 
-        // Load playlists
-        let playlists = config::Playlists::read()?;
+        let app_token = store.app_token();
+
+        let youtube_quota = store.youtube_quota(&youtube::quota_day());
 
         Ok(App {
+            _lock: lock,
             config,
-            playlists,
+            store,
             client,
             access_token,
+            app_token,
+            youtube_cache: cache::ResponseCache::new(config::CACHE_DIRECTORY),
+            youtube_quota,
+            youtube_units: 0,
+            youtube_dl_listings: std::collections::HashMap::new(),
         })
     }
 
@@ -104,10 +144,58 @@ impl App {
         backoff::Operation::retry(&mut op, &mut default_backoff()).unwrap()
     }
 
-    fn download_thumbnail(&self, video: &youtube::PlaylistItem) -> Option<String> {
+    /// The details of the videos from the api by video id, requested in batches.
+    fn get_video_details_batch(
+        &mut self,
+        video_ids: &[&str],
+    ) -> Result<std::collections::HashMap<String, youtube::VideoDetails>, String> {
+        let mut details = std::collections::HashMap::new();
+        for chunk in video_ids.chunks(youtube::VIDEOS_PER_REQUEST) {
+            println!("Getting details of {} videos.", chunk.len());
+            let url = youtube::make_videos_url(chunk, &self.config.youtube_api_key).unwrap();
+            let resource: youtube::VideosResource =
+                self.get_youtube_resource(url, youtube::Request::Videos)?;
+            details.extend(
+                resource
+                    .items
+                    .into_iter()
+                    .map(|video| (video.id.clone(), video.details())),
+            );
+        }
+        Ok(details)
+    }
+
+    /// Fails if the video cannot be accessed, for example because it is private or was deleted.
+    fn get_video_details(video: &youtube::PlaylistItem) -> Result<youtube::VideoDetails, String> {
+        println!("Getting details of video {}.", video.snippet.title);
+        let mut op = || {
+            youtube::get_video_details(&video.contentDetails.videoId).map_err(|err| {
+                if youtube::is_unavailable(&err) {
+                    return backoff::Error::Permanent(err);
+                }
+                println!("Error: {}\nRetrying...", err);
+                backoff::Error::Transient(err)
+            })
+        };
+        backoff::Operation::retry(&mut op, &mut default_backoff())
+            .map_err(|err| format!("the details of the video could not be looked up: {}", err))
+    }
+
+    /// Returns `None` if the upload time of the Soundcloud account is unlimited.
+    fn get_upload_seconds_left(&self) -> Option<u64> {
+        let mut op = || {
+            soundcloud::get_upload_seconds_left(&self.access_token, &self.client).map_err(|err| {
+                println!("Error: {}\nRetrying...", err);
+                backoff::Error::Transient(err)
+            })
+        };
+        backoff::Operation::retry(&mut op, &mut default_backoff()).unwrap()
+    }
+
+    fn download_thumbnail(&self, thumbnails: &youtube::Thumbnails) -> Option<String> {
         println!("Downloading thumbnail.");
         let mut op = || {
-            let url = &video.snippet.thumbnails.get_best_thumbnail().url;
+            let url = &thumbnails.get_best_thumbnail().url;
             let allowed_extensions = &["jpg", "png"];
             let start = url.rfind('.').ok_or_else(|| {
                 backoff::Error::Permanent(format!("thumbnail url {} has no extension", url))
@@ -141,60 +229,266 @@ impl App {
         }
     }
 
+    /// A page of the Youtube playlist. With the youtube-dl backend, the first page contains all
+    /// items.
     fn get_youtube_playlist_data(
-        &self,
-        url: reqwest::Url,
+        &mut self,
+        id: &str,
+        page_token: Option<&str>,
     ) -> Result<youtube::PlaylistItemsResource, String> {
         println!("Getting Youtube playlist data.");
-        let mut op = || -> Result<youtube::PlaylistItemsResource, backoff::Error<String>> {
-            self.client
-                .get(url.clone())
-                .send()
-                .map_err(|err| {
-                    backoff::Error::Transient(format!(
-                        "failed to send youtube playlist get request: {}",
-                        err
-                    ))
-                })
-                .and_then(|response| match response.status() {
-                    status if status.is_success() => Ok(response),
-                    status if status.is_client_error() => Err(backoff::Error::Permanent(format!(
-                        "response indicates client error: {}",
-                        status
-                    ))),
-                    status => Err(backoff::Error::Transient(format!(
-                        "response indicates server error: {}",
-                        status
-                    ))),
-                })?
-                .json()
-                .map_err(|err| {
-                    backoff::Error::Transient(format!(
-                        "failed to parse youtube playlist get response: {}",
-                        err
-                    ))
-                })
-                .map_err(|err| match err {
-                    backoff::Error::Transient(err) => {
-                        println!("Error: {}\nRetrying...", err);
-                        backoff::Error::Transient(err)
-                    }
-                    other => other,
+        match self.config.youtube_backend {
+            config::YoutubeBackend::Api => {
+                let mut url =
+                    youtube::make_playlist_items_url(id, &self.config.youtube_api_key).unwrap();
+                if let Some(token) = page_token {
+                    url.query_pairs_mut().append_pair("pageToken", token);
+                }
+                self.get_youtube_resource(url, youtube::Request::PlaylistItems)
+            }
+            config::YoutubeBackend::YoutubeDl => {
+                let (_, items) = self.list_playlist_with_youtube_dl(id)?;
+                Ok(youtube::PlaylistItemsResource {
+                    nextPageToken: None,
+                    pageInfo: youtube::PageInfo {
+                        totalResults: items.len() as u64,
+                    },
+                    items,
                 })
+            }
+        }
+    }
+
+    /// The metadata and items of the playlist. The playlist is listed once per run because
+    /// youtube-dl always lists all items.
+    fn list_playlist_with_youtube_dl(
+        &mut self,
+        id: &str,
+    ) -> Result<(youtube::Playlist, Vec<youtube::PlaylistItem>), String> {
+        if let Some(listing) = self.youtube_dl_listings.get(id) {
+            return Ok(listing.clone());
+        }
+        let mut op = || {
+            youtube::list_playlist_with_youtube_dl(id).map_err(|err| {
+                if youtube::is_unavailable(&err) {
+                    return backoff::Error::Permanent(err);
+                }
+                println!("Error: {}\nRetrying...", err);
+                backoff::Error::Transient(err)
+            })
         };
-        backoff::Operation::retry(&mut op, &mut default_backoff()).map_err(|err| {
-            format!(
-                "could not get the youtube playlist: {}. \
+        let listing =
+            backoff::Operation::retry(&mut op, &mut default_backoff()).map_err(|err| {
+                format!(
+                    "could not list the youtube playlist: {}. \
                  Make sure the id is set correctly in the config file.",
-                err
-            )
-        })
+                    err
+                )
+            })?;
+        self.youtube_dl_listings
+            .insert(id.to_string(), listing.clone());
+        Ok(listing)
+    }
+
+    /// The uploads playlist of a Youtube channel.
+    fn resolve_channel(&mut self, channel: &str) -> Result<String, String> {
+        println!(
+            "Getting the uploads playlist of Youtube channel {}.",
+            channel
+        );
+        for lookup in youtube::parse_channel(channel) {
+            let uploads = match self.config.youtube_backend {
+                config::YoutubeBackend::Api => {
+                    let url =
+                        youtube::make_channels_url(&lookup, &self.config.youtube_api_key).unwrap();
+                    let resource: youtube::ChannelsResource =
+                        self.get_youtube_resource(url, youtube::Request::Channels)?;
+                    resource
+                        .items
+                        .into_iter()
+                        .next()
+                        .map(|found| found.contentDetails.relatedPlaylists.uploads)
+                }
+                config::YoutubeBackend::YoutubeDl => {
+                    let mut op = || {
+                        youtube::get_uploads_playlist_with_youtube_dl(&lookup).map_err(|err| {
+                            println!("Error: {}\nRetrying...", err);
+                            backoff::Error::Transient(err)
+                        })
+                    };
+                    backoff::Operation::retry(&mut op, &mut default_backoff()).unwrap()
+                }
+            };
+            if let Some(uploads) = uploads {
+                return Ok(uploads);
+            }
+        }
+        Err(format!("the Youtube channel {} does not exist", channel))
+    }
+
+    fn get_youtube_playlist_metadata(&mut self, id: &str) -> Result<youtube::Playlist, String> {
+        println!("Getting Youtube playlist metadata.");
+        if self.config.youtube_backend == config::YoutubeBackend::YoutubeDl {
+            return self
+                .list_playlist_with_youtube_dl(id)
+                .map(|(metadata, _)| metadata);
+        }
+        let url = youtube::make_playlists_url(id, &self.config.youtube_api_key).unwrap();
+        let resource: youtube::PlaylistsResource =
+            self.get_youtube_resource(url, youtube::Request::Playlists)?;
+        resource
+            .items
+            .into_iter()
+            .next()
+            .ok_or_else(|| format!("the youtube playlist with id {} does not exist", id))
+    }
+
+    /// The reason why no more Youtube api requests can be sent today, if any.
+    fn youtube_quota_error(&self) -> Option<String> {
+        if self.youtube_quota.exhausted {
+            return Some(
+                "the Youtube api quota is used up. It resets at midnight Pacific time.".to_string(),
+            );
+        }
+        match self.config.youtube_daily_quota {
+            Some(budget) if self.youtube_quota.units + youtube::LIST_COST > budget => {
+                Some(format!(
+                    "the daily Youtube api budget of {} units is used up. \
+                 It resets at midnight Pacific time.",
+                    budget
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    /// Responses are cached with their ETag and requested conditionally so that unchanged
+    /// resources are not sent again. Every request is counted against the daily quota and the
+    /// usage is saved right away.
+    fn get_youtube_resource<T: serde::de::DeserializeOwned>(
+        &mut self,
+        url: reqwest::Url,
+        kind: youtube::Request,
+    ) -> Result<T, String> {
+        if let Some(err) = self.youtube_quota_error() {
+            return Err(err);
+        }
+        let cached = if kind.is_playlist() {
+            self.youtube_cache.get(url.as_str())
+        } else {
+            None
+        };
+        let requests = std::cell::Cell::new(0);
+        let quota_exceeded = std::cell::Cell::new(false);
+        let mut op = || -> Result<cache::Entry, backoff::Error<String>> {
+            requests.set(requests.get() + 1);
+            let mut request = self.client.get(url.clone());
+            if let Some(cached) = &cached {
+                request = request.header(reqwest::header::IF_NONE_MATCH, cached.etag.as_str());
+            }
+            let response = request.send().map_err(|err| {
+                backoff::Error::Transient(format!(
+                    "failed to send youtube {} request: {}",
+                    kind.name(),
+                    err
+                ))
+            })?;
+            match response.status() {
+                reqwest::StatusCode::NOT_MODIFIED if cached.is_some() => {
+                    let cached = cached.as_ref().unwrap();
+                    Ok(cache::Entry {
+                        etag: cached.etag.clone(),
+                        body: cached.body.clone(),
+                    })
+                }
+                status if status.is_success() => {
+                    let etag = response
+                        .headers()
+                        .get(reqwest::header::ETAG)
+                        .and_then(|etag| etag.to_str().ok())
+                        .unwrap_or_default()
+                        .to_string();
+                    response
+                        .json()
+                        .map(|body| cache::Entry { etag, body })
+                        .map_err(|err| {
+                            backoff::Error::Transient(format!(
+                                "failed to parse youtube {} response: {}",
+                                kind.name(),
+                                err
+                            ))
+                        })
+                }
+                reqwest::StatusCode::FORBIDDEN
+                    if youtube::is_quota_exceeded(&response.text().unwrap_or_default()) =>
+                {
+                    quota_exceeded.set(true);
+                    Err(backoff::Error::Permanent(
+                        "the Youtube api quota is used up. \
+                         It resets at midnight Pacific time."
+                            .to_string(),
+                    ))
+                }
+                status if status.is_client_error() => Err(backoff::Error::Permanent(format!(
+                    "response indicates client error: {}",
+                    status
+                ))),
+                status => Err(backoff::Error::Transient(format!(
+                    "response indicates server error: {}",
+                    status
+                ))),
+            }
+            .map_err(|err| match err {
+                backoff::Error::Transient(err) => {
+                    println!("Error: {}\nRetrying...", err);
+                    backoff::Error::Transient(err)
+                }
+                other => other,
+            })
+        };
+        let result = backoff::Operation::retry(&mut op, &mut default_backoff());
+        let units = requests.get() * youtube::LIST_COST;
+        self.youtube_units += units;
+        self.youtube_quota.units += units;
+        // Do not try again until the quota resets.
+        self.youtube_quota.exhausted |= quota_exceeded.get();
+        self.store.set_youtube_quota(&self.youtube_quota)?;
+        let entry = match result {
+            Ok(entry) => entry,
+            Err(backoff::Error::Permanent(err)) if quota_exceeded.get() => return Err(err),
+            Err(err) if kind.is_playlist() => {
+                return Err(format!(
+                    "could not get the youtube {}: {}. \
+                     Make sure the id is set correctly in the config file.",
+                    kind.name(),
+                    err
+                ))
+            }
+            Err(err) => {
+                return Err(format!(
+                    "could not get the youtube {}: {}",
+                    kind.name(),
+                    err
+                ))
+            }
+        };
+        let unchanged = cached
+            .as_ref()
+            .is_some_and(|cached| cached.etag == entry.etag);
+        if kind.is_playlist() && !unchanged && !entry.etag.is_empty() {
+            if let Err(err) = self.youtube_cache.put(url.as_str(), &entry) {
+                println!("Error: {}\nThe response will not be cached.", err);
+            }
+        }
+        serde_json::from_value(entry.body)
+            .map_err(|err| format!("failed to parse youtube {} response: {}", kind.name(), err))
     }
 
     fn upload_audio(
         &self,
         audio_path: &str,
         video: &youtube::PlaylistItem,
+        details: Option<&youtube::VideoDetails>,
         thumbnail_path: &Option<String>,
     ) -> u64 {
         println!("Uploading {} to Soundcloud.", audio_path);
@@ -206,11 +500,34 @@ impl App {
         metadata.insert("title", &video.snippet.title);
         metadata.insert("description", &video.snippet.description);
         metadata.insert("downloadable", "1");
+        let mut tags = vec![soundcloud::video_tag(&video.contentDetails.videoId)];
+        if let Some(details) = details {
+            // A video tag like `youtube:video=ID` would map the track to another video.
+            tags.extend(
+                details
+                    .tags
+                    .iter()
+                    .filter(|tag| !soundcloud::is_machine_tag(tag))
+                    .cloned(),
+            );
+        }
+        let tag_list = soundcloud::tag_list(&tags);
+        metadata.insert("tag_list", &tag_list);
+        let genre = details
+            .and_then(|details| details.category_id.as_deref())
+            .and_then(|category_id| self.config.genre(category_id));
+        if let Some(genre) = &genre {
+            metadata.insert("genre", genre);
+        }
+        let source_url = youtube::video_url(&video.contentDetails.videoId);
+        if self.config.purchase_url {
+            metadata.insert("purchase_url", &source_url);
+        }
         let datetime =
             chrono::DateTime::<chrono::offset::Utc>::from_str(&video.snippet.publishedAt);
         match datetime {
             Ok(datetime) => {
-                let date = datetime.date();
+                let date = datetime.date_naive();
                 year = date.year().to_string();
                 month = date.month().to_string();
                 day = date.day().to_string();
@@ -218,6 +535,8 @@ impl App {
                 metadata.insert("release_month", &month);
                 metadata.insert("release_day", &day);
             }
+            // The youtube-dl backend does not know the date.
+            Err(_) if video.snippet.publishedAt.is_empty() => {}
             Err(err) => println!(
                 "Failed to parse timedate string {}, \
                  release date will not be set on Soundcloud: {}.",
@@ -226,11 +545,16 @@ impl App {
         }
         metadata.insert("downloadable", "1");
         let mut op = || {
+            let mut progress = None;
             soundcloud::upload(
                 audio_path,
                 thumbnail_path,
                 &metadata,
-                &self.config.soundcloud_client_id,
+                move |sent, size| {
+                    progress
+                        .get_or_insert_with(|| util::ProgressPrinter::new(size))
+                        .update(sent)
+                },
                 &self.access_token,
                 &self.client,
             )
@@ -239,8 +563,114 @@ impl App {
                 backoff::Error::Transient(err)
             })
         };
-        let audio_id = backoff::Operation::retry(&mut op, &mut default_backoff()).unwrap();
+        backoff::Operation::retry(&mut op, &mut default_backoff()).unwrap()
+    }
+
+    /// A track of the Soundcloud account that was already uploaded from the video, found by the
+    /// tag with the video id or by the title. This prevents duplicates when the position or the
+    /// state was lost.
+    fn find_existing_track(
+        &self,
+        account_tracks: &mut Option<Vec<soundcloud::AccountTrack>>,
+        video: &youtube::PlaylistItem,
+    ) -> Option<u64> {
+        let tracks = account_tracks.get_or_insert_with(|| {
+            println!("Getting the tracks of the Soundcloud account.");
+            let mut op = || {
+                soundcloud::get_my_tracks(&self.access_token, &self.client).map_err(|err| {
+                    println!("Error: {}\nRetrying...", err);
+                    backoff::Error::Transient(err)
+                })
+            };
+            backoff::Operation::retry(&mut op, &mut default_backoff()).unwrap()
+        });
+        let tag = soundcloud::video_tag(&video.contentDetails.videoId);
+        if let Some(track) = tracks.iter().find(|track| track.has_tag(&tag)) {
+            println!(
+                "Video {} was already uploaded as Soundcloud track {}, using it instead of \
+                 uploading it again.",
+                video.snippet.title, track.id
+            );
+            return Some(track.id);
+        }
+        // Tracks that belong to another video must not be taken for this one because of the
+        // title, so only untagged tracks are compared by title.
+        let track = tracks
+            .iter()
+            .find(|track| !track.has_video_tag() && track.title == video.snippet.title)?;
+        println!(
+            "Video {} has the same title as the untagged Soundcloud track {}, using it instead \
+             of uploading the video.",
+            video.snippet.title, track.id
+        );
+        Some(track.id)
+    }
+
+    /// Wait until Soundcloud has processed the uploaded track. Returns the reason if it failed.
+    fn wait_for_processing(&self, audio_id: u64) -> Result<(), String> {
+        println!("Waiting for Soundcloud to process track {}.", audio_id);
+        let start = std::time::Instant::now();
+        loop {
+            let mut op = || {
+                soundcloud::get_track_status(audio_id, &self.access_token, &self.client).map_err(
+                    |err| {
+                        println!("Error: {}\nRetrying...", err);
+                        backoff::Error::Transient(err)
+                    },
+                )
+            };
+            let status = backoff::Operation::retry(&mut op, &mut default_backoff()).unwrap();
+            match status.as_ref().map(|status| status.state.as_str()) {
+                Some("finished") => return Ok(()),
+                Some("failed") => return Err("Soundcloud failed to process the track".to_string()),
+                None => return Err("the uploaded track does not exist anymore".to_string()),
+                Some(_) if start.elapsed() >= PROCESSING_TIMEOUT => {
+                    return Err("Soundcloud did not finish processing the track".to_string())
+                }
+                Some(_) => std::thread::sleep(PROCESSING_POLL_INTERVAL),
+            }
+        }
+    }
+
+    /// Upload the video and wait for it to be processed. Failed uploads are deleted and uploaded
+    /// again up to `UPLOAD_ATTEMPTS` times. Returns the reason of the last failure if none succeeded.
+    fn transfer_video(
+        &self,
+        audio_path: &str,
+        video: &youtube::PlaylistItem,
+        details: Option<&youtube::VideoDetails>,
+        thumbnail_path: &Option<String>,
+    ) -> Result<u64, String> {
+        let mut reason = String::new();
+        for attempt in 1..=UPLOAD_ATTEMPTS {
+            let audio_id = self.upload_audio(audio_path, video, details, thumbnail_path);
+            match self.wait_for_processing(audio_id) {
+                Ok(()) => return Ok(audio_id),
+                Err(err) => {
+                    println!(
+                        "Error: {} (attempt {} of {}).",
+                        err, attempt, UPLOAD_ATTEMPTS
+                    );
+                    self.delete_track(audio_id);
+                    reason = err;
+                }
+            }
+        }
+        Err(reason)
+    }
+
+    fn delete_track(&self, track_id: u64) {
+        println!("Deleting Soundcloud track with id {}.", track_id);
+        let mut op = || {
+            soundcloud::delete_track(track_id, &self.access_token, &self.client).map_err(|err| {
+                println!("Error: {}\nRetrying...", err);
+                backoff::Error::Transient(err)
+            })
+        };
+        backoff::Operation::retry(&mut op, &mut default_backoff()).unwrap();
+    }
 
+    fn clean_up(audio_path: &str, thumbnail_path: &Option<String>) {
         if let Some(path) = thumbnail_path {
             println!("Cleaning up thumbnail file.");
             if let Err(err) = std::fs::remove_file(path) {
@@ -252,20 +682,348 @@ impl App {
         if let Err(err) = std::fs::remove_file(audio_path) {
             println!("Error: {}\nFile will remain on disk.", err);
         };
-
-        audio_id
     }
 
-    fn add_to_playlist(&self, audio_id: u64, soundcloud_playlist_api_url: &str) {
+    /// Returns the tracks that the playlist does not contain after the update or `None` if the
+    /// playlist does not exist.
+    fn add_to_playlist(
+        &self,
+        audio_ids: &[u64],
+        soundcloud_playlist_api_url: &str,
+    ) -> Option<Vec<u64>> {
         println!(
-            "Adding uploaded audio track with id {} to Soundcloud playlist.",
-            audio_id
+            "Adding {} uploaded audio tracks to Soundcloud playlist.",
+            audio_ids.len()
         );
         let mut op = || {
             soundcloud::add_to_playlist(
-                audio_id,
+                audio_ids,
                 soundcloud_playlist_api_url,
-                &self.config.soundcloud_client_id,
+                &self.access_token,
+                &self.client,
+            )
+            .map_err(|err| {
+                println!("Error: {}\nRetrying...", err);
+                backoff::Error::Transient(err)
+            })
+        };
+        backoff::Operation::retry(&mut op, &mut default_backoff()).unwrap()
+    }
+
+    /// Record that the video will not be transferred and move the position past it.
+    fn skip_video(
+        &mut self,
+        index: usize,
+        playlist: &mut config::Playlist,
+        video: &youtube::PlaylistItem,
+        reason: String,
+    ) -> Result<(), String> {
+        println!("Skipping video {}: {}.", video.snippet.title, reason);
+        self.store.record_skip(&config::Skip {
+            youtube_playlist: playlist.youtube.clone(),
+            video_id: video.contentDetails.videoId.clone(),
+            reason,
+            time: chrono::Utc::now().to_rfc3339(),
+        })?;
+        playlist.position += 1;
+        self.store.update_playlist(index, playlist)
+    }
+
+    /// Returns `None` if the playlist does not exist.
+    fn missing_tracks(
+        &self,
+        audio_ids: &[u64],
+        soundcloud_playlist_api_url: &str,
+    ) -> Option<Vec<u64>> {
+        let mut op = || {
+            soundcloud::missing_tracks(
+                audio_ids,
+                soundcloud_playlist_api_url,
+                &self.access_token,
+                &self.client,
+            )
+            .map_err(|err| {
+                println!("Error: {}\nRetrying...", err);
+                backoff::Error::Transient(err)
+            })
+        };
+        backoff::Operation::retry(&mut op, &mut default_backoff()).unwrap()
+    }
+
+    /// Check that the playlist contains `audio_ids` after an update that dropped the tracks in
+    /// `missing` and add the missing tracks again after a while. Returns the tracks that are still
+    /// missing after `MEMBERSHIP_RETRIES` attempts.
+    fn verify_membership(
+        &mut self,
+        index: usize,
+        playlist: &mut config::Playlist,
+        soundcloud_playlist_api_url: &mut String,
+        audio_ids: &[u64],
+        mut missing: Vec<u64>,
+    ) -> Result<Vec<u64>, String> {
+        let mut retries = 0;
+        loop {
+            // Soundcloud can drop tracks after it answered the update, so the playlist is read
+            // again.
+            if missing.is_empty() {
+                missing = self.with_soundcloud_playlist(
+                    index,
+                    playlist,
+                    soundcloud_playlist_api_url,
+                    |app, _, api_url| Ok(app.missing_tracks(audio_ids, api_url)),
+                )?;
+            }
+            if missing.is_empty() || retries == MEMBERSHIP_RETRIES {
+                return Ok(missing);
+            }
+            retries += 1;
+            println!(
+                "{} tracks are missing from the Soundcloud playlist. Adding them again.",
+                missing.len()
+            );
+            std::thread::sleep(MEMBERSHIP_RETRY_DELAY);
+            let dropped = missing;
+            missing = self.with_soundcloud_playlist(
+                index,
+                playlist,
+                soundcloud_playlist_api_url,
+                |app, _, api_url| Ok(app.add_to_playlist(&dropped, api_url)),
+            )?;
+        }
+    }
+
+    /// Add the uploaded tracks that were not added yet to the Soundcloud playlist with one update.
+    fn flush_batch(
+        &mut self,
+        index: usize,
+        playlist: &mut config::Playlist,
+        soundcloud_playlist_api_url: &mut String,
+    ) -> Result<(), String> {
+        if playlist.unadded_tracks.is_empty() {
+            return Ok(());
+        }
+        let audio_ids = playlist.unadded_tracks.clone();
+        let dropped = self.with_soundcloud_playlist(
+            index,
+            playlist,
+            soundcloud_playlist_api_url,
+            |app, _, api_url| Ok(app.add_to_playlist(&audio_ids, api_url)),
+        )?;
+        let missing = self.verify_membership(
+            index,
+            playlist,
+            soundcloud_playlist_api_url,
+            &audio_ids,
+            dropped,
+        )?;
+        for track_id in missing {
+            println!(
+                "Error: track {} could not be added to the Soundcloud playlist. Add it manually.",
+                track_id
+            );
+        }
+        playlist.unadded_tracks.clear();
+        self.store.update_playlist(index, playlist)
+    }
+
+    /// The videos of the playlist that were transferred or skipped before.
+    fn handled_videos(
+        &self,
+        playlist: &config::Playlist,
+    ) -> Result<std::collections::HashSet<String>, String> {
+        let transferred = self.store.transfers()?.into_iter().filter(|transfer| {
+            transfer.youtube_playlist == playlist.youtube
+                && transfer.soundcloud_playlist == playlist.soundcloud
+        });
+        let skipped = self
+            .store
+            .skipped()?
+            .into_iter()
+            .filter(|skip| skip.youtube_playlist == playlist.youtube);
+        Ok(transferred
+            .map(|transfer| transfer.video_id)
+            .chain(skipped.map(|skip| skip.video_id))
+            .collect())
+    }
+
+    /// Make the Soundcloud playlist contain the uploaded tracks of the videos in `video_ids` in the
+    /// same order. Tracks that were not uploaded by this program are kept at the end. Returns
+    /// `None` if the playlist does not exist.
+    fn mirror_playlist(
+        &self,
+        playlist: &config::Playlist,
+        video_ids: &[String],
+        soundcloud_playlist_api_url: &str,
+    ) -> Result<Option<()>, String> {
+        println!("Mirroring Youtube playlist to Soundcloud playlist.");
+        let transfers = self.store.transfers()?;
+        // Later transfers of the same video take precedence.
+        let ledger: std::collections::HashMap<String, u64> = transfers
+            .iter()
+            .filter(|transfer| {
+                transfer.youtube_playlist == playlist.youtube
+                    && transfer.soundcloud_playlist == playlist.soundcloud
+            })
+            .map(|transfer| (transfer.video_id.clone(), transfer.track_id))
+            .collect();
+
+        let mut op = || {
+            soundcloud::get_tracks(
+                soundcloud_playlist_api_url,
+                &self.access_token,
+                &self.client,
+            )
+            .map_err(|err| {
+                println!("Error: {}\nRetrying...", err);
+                backoff::Error::Transient(err)
+            })
+        };
+        let current: Vec<u64> =
+            match backoff::Operation::retry(&mut op, &mut default_backoff()).unwrap() {
+                Some(response) => response.tracks.iter().map(|track| track.id).collect(),
+                None => return Ok(None),
+            };
+
+        let MirrorPlan { desired, orphans } = plan_mirror(video_ids, &ledger, &current);
+
+        if desired == current {
+            println!("Soundcloud playlist is already up to date.");
+        } else {
+            println!(
+                "Updating Soundcloud playlist to {} tracks, removing {}.",
+                desired.len(),
+                orphans.len()
+            );
+            let mut op = || {
+                soundcloud::set_tracks(
+                    &desired,
+                    soundcloud_playlist_api_url,
+                    &self.access_token,
+                    &self.client,
+                )
+                .map_err(|err| {
+                    println!("Error: {}\nRetrying...", err);
+                    backoff::Error::Transient(err)
+                })
+            };
+            backoff::Operation::retry(&mut op, &mut default_backoff()).unwrap();
+        }
+
+        if playlist.delete_orphans {
+            let protected = protected_tracks(&transfers, playlist);
+            for track_id in orphans {
+                if protected.contains(&track_id) {
+                    println!(
+                        "Track {} is orphaned but is kept because it was not uploaded for this \
+                         playlist only.",
+                        track_id
+                    );
+                    continue;
+                }
+                println!("Track {} is orphaned.", track_id);
+                self.delete_track(track_id);
+            }
+        }
+        Ok(Some(()))
+    }
+
+    /// Create a Soundcloud playlist with the title, description and thumbnail of the Youtube
+    /// playlist.
+    fn create_soundcloud_playlist(
+        &self,
+        metadata: &youtube::Playlist,
+    ) -> Result<soundcloud::PlaylistResponse, String> {
+        let artwork_path = self.download_thumbnail(&metadata.snippet.thumbnails);
+        println!(
+            "Creating Soundcloud playlist with title {}.",
+            metadata.snippet.title
+        );
+        // A failed request might still have created the playlist, for example if the response
+        // timed out, so it is looked up before trying again.
+        let mut attempted = false;
+        let mut op = || {
+            if attempted {
+                let existing = soundcloud::find_my_playlist(
+                    &metadata.snippet.title,
+                    &self.access_token,
+                    &self.client,
+                )
+                .map_err(|err| {
+                    println!("Error: {}\nRetrying...", err);
+                    backoff::Error::Transient(err)
+                })?;
+                if let Some(existing) = existing {
+                    println!("The playlist was created by the failed request.");
+                    return Ok(existing);
+                }
+            }
+            attempted = true;
+            soundcloud::create_playlist(
+                &metadata.snippet.title,
+                &metadata.snippet.description,
+                &artwork_path,
+                &self.access_token,
+                &self.client,
+            )
+            .map_err(|err| {
+                println!("Error: {}\nRetrying...", err);
+                backoff::Error::Transient(err)
+            })
+        };
+        let playlist = backoff::Operation::retry(&mut op, &mut default_backoff()).unwrap();
+
+        if let Some(path) = artwork_path {
+            println!("Cleaning up thumbnail file.");
+            if let Err(err) = std::fs::remove_file(path) {
+                println!("Error: {}\nFile will remain on disk.", err);
+            }
+        };
+        Ok(playlist)
+    }
+
+    /// Update the title, description and artwork of the Soundcloud playlist if they differ from
+    /// the Youtube playlist. Returns whether the artwork was updated or `None` if the playlist does
+    /// not exist.
+    fn sync_playlist_metadata(
+        &self,
+        playlist: &config::Playlist,
+        metadata: &youtube::Playlist,
+        soundcloud_playlist_api_url: &str,
+    ) -> Option<bool> {
+        let mut op = || {
+            soundcloud::get_playlist_metadata(
+                soundcloud_playlist_api_url,
+                &self.access_token,
+                &self.client,
+            )
+            .map_err(|err| {
+                println!("Error: {}\nRetrying...", err);
+                backoff::Error::Transient(err)
+            })
+        };
+        let current = backoff::Operation::retry(&mut op, &mut default_backoff()).unwrap()?;
+
+        let thumbnail_url = &metadata.snippet.thumbnails.get_best_thumbnail().url;
+        let update_artwork = playlist.synced_thumbnail.as_ref() != Some(thumbnail_url);
+        if current.title == metadata.snippet.title
+            && current.description.as_deref().unwrap_or("") == metadata.snippet.description
+            && !update_artwork
+        {
+            return Some(false);
+        }
+
+        println!("Updating Soundcloud playlist metadata.");
+        let artwork_path = if update_artwork {
+            self.download_thumbnail(&metadata.snippet.thumbnails)
+        } else {
+            None
+        };
+        let mut op = || {
+            soundcloud::update_playlist_metadata(
+                soundcloud_playlist_api_url,
+                &metadata.snippet.title,
+                &metadata.snippet.description,
+                &artwork_path,
                 &self.access_token,
                 &self.client,
             )
@@ -275,14 +1033,57 @@ impl App {
             })
         };
         backoff::Operation::retry(&mut op, &mut default_backoff()).unwrap();
+
+        match artwork_path {
+            Some(path) => {
+                println!("Cleaning up thumbnail file.");
+                if let Err(err) = std::fs::remove_file(path) {
+                    println!("Error: {}\nFile will remain on disk.", err);
+                }
+                Some(true)
+            }
+            None => Some(false),
+        }
     }
 
-    fn resolve_soundcloud_playlist_url(&self, url: &str) -> Result<String, String> {
-        println!("Resolving Soundcloud playlist url {}.", url);
+    /// The api url of the Soundcloud playlist. It is only resolved if it is not cached yet, the
+    /// url changed or `refresh` is set because a request against the cached api url returned 404.
+    fn soundcloud_playlist_api_url(
+        &mut self,
+        index: usize,
+        playlist: &mut config::Playlist,
+        refresh: bool,
+    ) -> Result<String, String> {
+        if refresh {
+            println!("The Soundcloud playlist was not found at the cached api url.");
+        } else if let Some(resolved) = &playlist.resolved {
+            if resolved.url == playlist.soundcloud {
+                return Ok(resolved.api_url.clone());
+            }
+        }
+        let api_url = self.resolve_soundcloud_playlist_url(&playlist.soundcloud)?;
+        playlist.resolved = Some(config::ResolvedUrl {
+            url: playlist.soundcloud.clone(),
+            api_url: api_url.clone(),
+        });
+        self.store.update_playlist(index, playlist)?;
+        Ok(api_url)
+    }
+
+    /// The application access token, renewed if it expired.
+    fn app_access_token(&mut self) -> Result<String, String> {
+        match &self.app_token {
+            Some(token) if !token.is_expired() => Ok(token.access_token.clone()),
+            _ => self.renew_app_token(),
+        }
+    }
+
+    fn renew_app_token(&mut self) -> Result<String, String> {
+        println!("Getting Soundcloud application access token.");
         let mut op = || {
-            soundcloud::playlist_url_to_api_url(
-                &url,
+            soundcloud::authenticate_client_credentials(
                 &self.config.soundcloud_client_id,
+                &self.config.soundcloud_client_secret,
                 &self.client,
             )
             .map_err(|err| {
@@ -290,80 +1091,655 @@ impl App {
                 backoff::Error::Transient(err)
             })
         };
-        match backoff::Operation::retry(&mut op, &mut default_backoff()).unwrap() {
-            Some(url) => Ok(url),
-            None => Err("The Soundcloud playlist url is not valid. \
-                            Make sure you correctly set the full url in the config file."
-                .to_string()),
+        let response = match backoff::Operation::retry(&mut op, &mut default_backoff()).unwrap() {
+            Some(response) => response,
+            None => return Err("The Soundcloud application details are not valid.".to_string()),
+        };
+        let lifetime = response
+            .expires_in
+            .map_or(APP_TOKEN_LIFETIME, |seconds| seconds as i64);
+        let token = config::AppToken {
+            access_token: response.access_token,
+            expires_at: (chrono::Utc::now() + chrono::Duration::seconds(lifetime)).to_rfc3339(),
+        };
+        self.store.set_app_token(&token)?;
+        self.app_token = Some(token.clone());
+        Ok(token.access_token)
+    }
+
+    /// Run `request` against the api url of the Soundcloud playlist. If it returns `None` because
+    /// the playlist was not found, the cached api url might be outdated so the url is resolved
+    /// again and `request` repeated once.
+    fn with_soundcloud_playlist<T>(
+        &mut self,
+        index: usize,
+        playlist: &mut config::Playlist,
+        soundcloud_playlist_api_url: &mut String,
+        mut request: impl FnMut(&App, &config::Playlist, &str) -> Result<Option<T>, String>,
+    ) -> Result<T, String> {
+        if let Some(result) = request(self, playlist, soundcloud_playlist_api_url)? {
+            return Ok(result);
+        }
+        *soundcloud_playlist_api_url = self.soundcloud_playlist_api_url(index, playlist, true)?;
+        request(self, playlist, soundcloud_playlist_api_url)?
+            .ok_or_else(|| playlist_not_found(&playlist.soundcloud))
+    }
+
+    /// If the application access token is rejected, a new one is requested once.
+    fn resolve_soundcloud_playlist_url(&mut self, url: &str) -> Result<String, String> {
+        println!("Resolving Soundcloud playlist url {}.", url);
+        let mut renewed = false;
+        loop {
+            let app_access_token = self.app_access_token()?;
+            let mut op = || {
+                soundcloud::resolve(url, &app_access_token, &self.client).map_err(|err| {
+                    println!("Error: {}\nRetrying...", err);
+                    backoff::Error::Transient(err)
+                })
+            };
+            match backoff::Operation::retry(&mut op, &mut default_backoff()).unwrap() {
+                soundcloud::Resolved::Location(url) => return Ok(url),
+                soundcloud::Resolved::NotFound => {
+                    return Err("The Soundcloud playlist url is not valid. \
+                                Make sure you correctly set the full url in the config file."
+                        .to_string())
+                }
+                soundcloud::Resolved::Unauthorized if !renewed => {
+                    println!("The Soundcloud application access token was rejected.");
+                    self.renew_app_token()?;
+                    renewed = true;
+                }
+                soundcloud::Resolved::Unauthorized => {
+                    return Err("Soundcloud rejected a new application access token.".to_string())
+                }
+            }
         }
     }
 
+    /// Transfer all playlists and report how much of the Youtube api quota was used, even if the
+    /// transfer failed.
     fn run(&mut self) -> Result<(), String> {
+        let result = self.run_playlists();
+        println!(
+            "Used {} Youtube api quota units in this run and {} today.",
+            self.youtube_units, self.youtube_quota.units
+        );
+        result
+    }
+
+    fn run_playlists(&mut self) -> Result<(), String> {
         println!();
-        for playlist in self.playlists.playlists.iter() {
-            println!(
-                "Starting work on Youtube playlist with id: {}.",
-                playlist.youtube
-            );
+        // The tracks of the Soundcloud account, loaded when they are first needed.
+        let mut account_tracks = None;
+        let mut upload_seconds_left = self.get_upload_seconds_left();
+        if let Some(left) = upload_seconds_left {
+            println!("{} seconds of upload time are left on Soundcloud.", left);
+        }
+        for (index, mut playlist) in self.store.playlists().into_iter().enumerate() {
+            if let Some(channel) = playlist.channel.clone() {
+                if playlist.youtube.is_empty()
+                    || playlist.resolved_channel.as_ref() != Some(&channel)
+                {
+                    playlist.youtube = self.resolve_channel(&channel)?;
+                    playlist.resolved_channel = Some(channel);
+                    self.store.update_playlist(index, &playlist)?;
+                }
+            }
+            // The uploads of a channel are listed newest first so all pages are read and reversed
+            // before any video is transferred.
+            let newest_first = playlist.channel.is_some();
+            // The metadata is only needed to create or sync the Soundcloud playlist and the
+            // videos can be transferred without it.
+            let metadata = if playlist.soundcloud.is_empty() || playlist.sync_metadata {
+                match self.get_youtube_playlist_metadata(&playlist.youtube) {
+                    Ok(metadata) => Some(metadata),
+                    Err(err) => {
+                        println!("Error: {}", err);
+                        None
+                    }
+                }
+            } else {
+                None
+            };
+            match &metadata {
+                Some(metadata) => println!(
+                    "Starting work on Youtube playlist {} with id: {}.",
+                    metadata.snippet.title, playlist.youtube
+                ),
+                None => println!(
+                    "Starting work on Youtube playlist with id: {}.",
+                    playlist.youtube
+                ),
+            }
 
-            let soundcloud_playlist_api_url =
-                self.resolve_soundcloud_playlist_url(&playlist.soundcloud)?;
+            let mut soundcloud_playlist_api_url = if playlist.soundcloud.is_empty() {
+                let metadata = match &metadata {
+                    Some(metadata) => metadata,
+                    None => {
+                        println!(
+                            "The Soundcloud playlist cannot be created without the metadata of \
+                             the Youtube playlist. Trying again on the next run.\n"
+                        );
+                        continue;
+                    }
+                };
+                let created = self.create_soundcloud_playlist(metadata)?;
+                println!("Created Soundcloud playlist {}.", created.permalink_url);
+                playlist.soundcloud = created.permalink_url.clone();
+                playlist.resolved = Some(config::ResolvedUrl {
+                    url: created.permalink_url,
+                    api_url: created.uri.clone(),
+                });
+                self.store.update_playlist(index, &playlist)?;
+                created.uri
+            } else {
+                let mut api_url = self.soundcloud_playlist_api_url(index, &mut playlist, false)?;
+                if let (true, Some(metadata)) = (playlist.sync_metadata, &metadata) {
+                    let artwork_updated = self.with_soundcloud_playlist(
+                        index,
+                        &mut playlist,
+                        &mut api_url,
+                        |app, playlist, api_url| {
+                            Ok(app.sync_playlist_metadata(playlist, metadata, api_url))
+                        },
+                    )?;
+                    if artwork_updated {
+                        playlist.synced_thumbnail =
+                            Some(metadata.snippet.thumbnails.get_best_thumbnail().url.clone());
+                        self.store.update_playlist(index, &playlist)?;
+                    }
+                }
+                api_url
+            };
+            // Add the tracks that were uploaded before an interruption.
+            self.flush_batch(index, &mut playlist, &mut soundcloud_playlist_api_url)?;
 
-            let mut url =
-                youtube::make_playlist_items_url(&playlist.youtube, &self.config.youtube_api_key)
-                    .unwrap();
-            let previous_position = playlist.position.get();
-            loop {
-                let resource = self.get_youtube_playlist_data(url.clone())?;
+            // The token of the current page, `None` for the first page. Unless the whole playlist
+            // is needed for mirroring, start at the page that contains the position.
+            let mut page_token = None;
+            if let Some(page) = playlist.page.as_ref().filter(|page| {
+                !playlist.mirror
+                    && !newest_first
+                    && page.youtube == playlist.youtube
+                    && page.position <= playlist.position
+            }) {
+                println!(
+                    "Starting at the page of the Youtube playlist with position {}.",
+                    page.position
+                );
+                page_token = Some(page.token.clone());
+            }
+            let mut seeking = page_token.is_some();
+            let previous_position = playlist.position;
+            // In mirror mode, removals on Youtube shift the positions so new videos are the ones
+            // that were not handled before instead of the ones after the position.
+            let mut handled = if playlist.mirror {
+                Some(self.handled_videos(&playlist)?)
+            } else {
+                None
+            };
+            // All videos of the Youtube playlist in order, only collected in mirror mode.
+            let mut video_ids = Vec::new();
+            // Whether all videos of the playlist were handled.
+            let mut complete = true;
+            // Items of all pages so far of a newest first playlist.
+            let mut all_items = Vec::new();
+            'pages: loop {
+                let mut resource = if seeking {
+                    seeking = false;
+                    let expected = playlist.page.as_ref().map(|page| page.position);
+                    match self.get_youtube_playlist_data(&playlist.youtube, page_token.as_deref()) {
+                        Ok(resource)
+                            if resource.items.first().map(|video| video.snippet.position)
+                                == expected =>
+                        {
+                            resource
+                        }
+                        // Scanning the whole playlist would not work either.
+                        Err(err) if self.youtube_quota_error().is_some() => return Err(err),
+                        _ => {
+                            println!(
+                                "The stored page token is not valid anymore. \
+                                 Scanning the whole Youtube playlist."
+                            );
+                            page_token = None;
+                            continue 'pages;
+                        }
+                    }
+                } else {
+                    self.get_youtube_playlist_data(&playlist.youtube, page_token.as_deref())?
+                };
+                if newest_first {
+                    all_items.append(&mut resource.items);
+                    if let Some(token) = resource.nextPageToken {
+                        page_token = Some(token);
+                        continue;
+                    }
+                    all_items.reverse();
+                    for (position, video) in all_items.iter_mut().enumerate() {
+                        video.snippet.position = position as u64;
+                    }
+                    resource.items = std::mem::take(&mut all_items);
+                }
+                if playlist.mirror {
+                    video_ids.extend(
+                        resource
+                            .items
+                            .iter()
+                            .map(|video| video.contentDetails.videoId.clone()),
+                    );
+                }
 
-                for video in resource
+                let new_videos: Vec<&youtube::PlaylistItem> = resource
                     .items
                     .iter()
-                    .filter(|x| x.snippet.position >= previous_position)
-                {
-                    let filename = App::download_audio(&video);
+                    .filter(|video| match &mut handled {
+                        // A video can be in the playlist more than once.
+                        Some(handled) => handled.insert(video.contentDetails.videoId.clone()),
+                        None => video.snippet.position >= previous_position,
+                    })
+                    .collect();
+
+                // With the api, the details of all new videos of the page are requested at once.
+                let mut video_details = match self.config.youtube_backend {
+                    config::YoutubeBackend::Api => {
+                        let video_ids: Vec<&str> = new_videos
+                            .iter()
+                            .map(|video| video.contentDetails.videoId.as_str())
+                            .collect();
+                        self.get_video_details_batch(&video_ids)?
+                    }
+                    config::YoutubeBackend::YoutubeDl => std::collections::HashMap::new(),
+                };
 
-                    let thumbnail_path = self.download_thumbnail(&video);
+                for video in new_videos {
+                    let mut details = video_details.remove(&video.contentDetails.videoId);
+                    if details.is_none() && playlist.filters.needs_details(video) {
+                        match App::get_video_details(video) {
+                            Ok(found) => details = Some(found),
+                            Err(reason) => {
+                                self.skip_video(index, &mut playlist, video, reason)?;
+                                continue;
+                            }
+                        }
+                    }
+                    // Only youtube-dl knows the orientation that tells shorts of up to three
+                    // minutes apart.
+                    if let Some(details) = details.as_mut().filter(|details| {
+                        self.config.youtube_backend == config::YoutubeBackend::Api
+                            && playlist.filters.needs_orientation(details)
+                    }) {
+                        match App::get_video_details(video) {
+                            Ok(found) => details.vertical = found.vertical,
+                            Err(reason) => {
+                                self.skip_video(index, &mut playlist, video, reason)?;
+                                continue;
+                            }
+                        }
+                    }
+                    if let Some(reason) = playlist.filters.rejection(video, details.as_ref()) {
+                        self.skip_video(index, &mut playlist, video, reason)?;
+                        continue;
+                    }
 
-                    let audio_id = self.upload_audio(&filename, &video, &thumbnail_path);
+                    let existing = self.find_existing_track(&mut account_tracks, video);
+                    // The api does not report the duration of livestreams.
+                    let unknown_duration = match &details {
+                        Some(details) => {
+                            details.duration.is_none()
+                                && self.config.youtube_backend == config::YoutubeBackend::Api
+                        }
+                        None => true,
+                    };
+                    if existing.is_none() && upload_seconds_left.is_some() && unknown_duration {
+                        match (App::get_video_details(video), details.as_mut()) {
+                            (Ok(found), Some(details)) => details.duration = found.duration,
+                            (Ok(found), None) => details = Some(found),
+                            (Err(reason), _) => {
+                                self.skip_video(index, &mut playlist, video, reason)?;
+                                continue;
+                            }
+                        }
+                    }
 
-                    self.add_to_playlist(audio_id, &soundcloud_playlist_api_url);
+                    // The track id and whether it already existed.
+                    let result = match existing {
+                        Some(track_id) => Ok((track_id, true)),
+                        None => {
+                            let duration = upload_seconds_left.map(|left| {
+                                (left, details.as_ref().and_then(|details| details.duration))
+                            });
+                            if let Some((_, None)) = duration {
+                                println!(
+                                    "The length of the video is unknown, so it cannot be checked \
+                                     against the upload time left on Soundcloud."
+                                );
+                            }
+                            match duration {
+                                Some((left, Some(duration))) if duration > left => {
+                                    let reason = format!(
+                                        "the video is {} seconds long but only {} seconds of \
+                                         upload time are left on the Soundcloud account",
+                                        duration, left
+                                    );
+                                    if self.config.quota_exceeded == config::QuotaExceeded::Defer {
+                                        println!(
+                                            "Deferring the remaining videos of the playlist to \
+                                             the next run because {}.",
+                                            reason
+                                        );
+                                        complete = false;
+                                        break 'pages;
+                                    }
+                                    Err(reason)
+                                }
+                                _ => {
+                                    let filename = App::download_audio(video);
 
-                    // Save new playlist position
-                    playlist.position.set(playlist.position.get() + 1);
-                    self.playlists.write_safe()?;
-                }
-                match resource.nextPageToken {
-                    Some(token) => {
-                        url = youtube::make_playlist_items_url(
-                            &playlist.youtube,
-                            &self.config.youtube_api_key,
-                        )
-                        .unwrap();
-                        url.query_pairs_mut().append_pair("pageToken", &token);
-                        continue;
+                                    let thumbnail_path =
+                                        self.download_thumbnail(&video.snippet.thumbnails);
+
+                                    let result = self.transfer_video(
+                                        &filename,
+                                        video,
+                                        details.as_ref(),
+                                        &thumbnail_path,
+                                    );
+                                    App::clean_up(&filename, &thumbnail_path);
+                                    if let (Ok(_), Some((left, Some(duration)))) =
+                                        (&result, duration)
+                                    {
+                                        upload_seconds_left = Some(left - duration);
+                                    }
+                                    result.map(|audio_id| (audio_id, false)).map_err(|reason| {
+                                        format!("the video could not be uploaded: {}", reason)
+                                    })
+                                }
+                            }
+                        }
+                    };
+                    let (audio_id, reused) = match result {
+                        Ok(result) => result,
+                        Err(reason) => {
+                            self.skip_video(index, &mut playlist, video, reason)?;
+                            continue;
+                        }
+                    };
+
+                    // The upload is recorded right away so that it is not repeated after an
+                    // interruption. Only adding it to the playlist is batched.
+                    playlist.position += 1;
+                    playlist.unadded_tracks.push(audio_id);
+                    self.store.record_transfer(
+                        index,
+                        &playlist,
+                        &config::Transfer {
+                            youtube_playlist: playlist.youtube.clone(),
+                            soundcloud_playlist: playlist.soundcloud.clone(),
+                            video_id: video.contentDetails.videoId.clone(),
+                            track_id: audio_id,
+                            title: video.snippet.title.clone(),
+                            time: chrono::Utc::now().to_rfc3339(),
+                            reused,
+                        },
+                    )?;
+                    if playlist.unadded_tracks.len() >= self.config.batch_size() {
+                        self.flush_batch(index, &mut playlist, &mut soundcloud_playlist_api_url)?;
                     }
-                    None => {
-                        break;
+                }
+                // Remember the last page that starts before the position for the next run.
+                if let (Some(token), Some(first), false) =
+                    (&page_token, resource.items.first(), newest_first)
+                {
+                    let position = first.snippet.position;
+                    let known = playlist.page.as_ref().is_some_and(|page| {
+                        page.youtube == playlist.youtube && &page.token == token
+                    });
+                    if position <= playlist.position && !known {
+                        playlist.page = Some(config::PageStart {
+                            youtube: playlist.youtube.clone(),
+                            token: token.clone(),
+                            position,
+                        });
+                        self.store.update_playlist(index, &playlist)?;
                     }
+                }
+                match resource.nextPageToken {
+                    Some(token) => page_token = Some(token),
+                    None => break,
                 };
             }
+            self.flush_batch(index, &mut playlist, &mut soundcloud_playlist_api_url)?;
+            if playlist.mirror && complete {
+                self.with_soundcloud_playlist(
+                    index,
+                    &mut playlist,
+                    &mut soundcloud_playlist_api_url,
+                    |app, playlist, api_url| app.mirror_playlist(playlist, &video_ids, api_url),
+                )?;
+                // Every video of the playlist has been handled.
+                playlist.position = video_ids.len() as u64;
+                self.store.update_playlist(index, &playlist)?;
+            }
             println!("Done.\n");
         }
         Ok(())
     }
 }
 
+/// The tracks that must not be deleted when they become orphans of `playlist`: tracks that
+/// existed before they were added and tracks that were also transferred for another mapping.
+fn protected_tracks(
+    transfers: &[config::Transfer],
+    playlist: &config::Playlist,
+) -> std::collections::HashSet<u64> {
+    transfers
+        .iter()
+        .filter(|transfer| {
+            transfer.reused
+                || transfer.youtube_playlist != playlist.youtube
+                || transfer.soundcloud_playlist != playlist.soundcloud
+        })
+        .map(|transfer| transfer.track_id)
+        .collect()
+}
+
+/// How a mirrored Soundcloud playlist is updated.
+#[derive(Debug, PartialEq)]
+struct MirrorPlan {
+    /// The tracks of the playlist after the update in this order.
+    desired: Vec<u64>,
+    /// Tracks uploaded by this program whose video is no longer in the Youtube playlist.
+    orphans: Vec<u64>,
+}
+
+/// The update of a Soundcloud playlist with the tracks `current` that follows the videos
+/// `video_ids`. `ledger` maps the videos to the tracks they were uploaded as. Tracks in the ledger
+/// follow the order of their videos, each once, and the other tracks are kept at the end in
+/// their current order.
+fn plan_mirror(
+    video_ids: &[String],
+    ledger: &std::collections::HashMap<String, u64>,
+    current: &[u64],
+) -> MirrorPlan {
+    let uploaded: std::collections::HashSet<u64> = ledger.values().copied().collect();
+    let mut desired: Vec<u64> = Vec::new();
+    for track_id in video_ids.iter().filter_map(|video_id| ledger.get(video_id)) {
+        if !desired.contains(track_id) {
+            desired.push(*track_id);
+        }
+    }
+    let orphans: Vec<u64> = current
+        .iter()
+        .filter(|track_id| uploaded.contains(track_id) && !desired.contains(track_id))
+        .copied()
+        .collect();
+    desired.extend(
+        current
+            .iter()
+            .filter(|track_id| !uploaded.contains(track_id)),
+    );
+    MirrorPlan { desired, orphans }
+}
+
+/// Import `playlists.json` and `state.json` into the SQLite database.
+#[cfg(feature = "sqlite")]
+fn migrate_to_sqlite(wait: bool) -> Result<(), String> {
+    let _lock = lock::Lock::acquire(lock::LOCK_FILE, wait)?;
+    let config = config::Config::read()?;
+    let mut json = store::JsonStore::open()?;
+    store::adopt_legacy_access_token(&mut json, &config)?;
+    let path = store::sqlite_path(&config);
+    println!(
+        "Importing {} playlists into {}.",
+        json.playlists.playlists.len(),
+        path
+    );
+    sqlite::SqliteStore::open(path)?.import(&json)?;
+    println!(
+        "Done. Set \"store\": \"sqlite\" in {} to use it.",
+        config::config_path()
+    );
+    Ok(())
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn migrate_to_sqlite(_wait: bool) -> Result<(), String> {
+    Err("this build does not support sqlite. Rebuild with --features sqlite.".to_string())
+}
+
+fn playlist_not_found(url: &str) -> String {
+    format!("the Soundcloud playlist {} does not exist anymore", url)
+}
+
+const USAGE: &str = "Usage: youtube_to_soundcloud [--wait] [validate | migrate-sqlite]";
+
 fn main() {
-    let mut app = match App::new() {
-        Ok(app) => app,
-        Err(err) => {
-            println!("Error: {}", err);
-            return;
+    let mut wait = false;
+    let mut command = None;
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--wait" => wait = true,
+            "validate" | "migrate-sqlite" if command.is_none() => command = Some(arg),
+            other => {
+                println!("Error: unknown argument {}.\n{}", other, USAGE);
+                std::process::exit(2);
+            }
         }
-    };
+    }
 
-    if let Err(err) = app.run() {
+    // Validation does not write anything so it does not need the lock.
+    let result = match command.as_deref() {
+        Some("validate") => validate::validate(),
+        Some("migrate-sqlite") => migrate_to_sqlite(wait),
+        _ => App::new(wait).and_then(|mut app| app.run()),
+    };
+    if let Err(err) = result {
         println!("Error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    fn ledger(entries: &[(&str, u64)]) -> std::collections::HashMap<String, u64> {
+        entries
+            .iter()
+            .map(|(video_id, track_id)| (video_id.to_string(), *track_id))
+            .collect()
+    }
+
+    #[test]
+    fn tracks_follow_the_order_of_the_videos() {
+        let plan = plan_mirror(
+            &ids(&["c", "a", "b"]),
+            &ledger(&[("a", 1), ("b", 2), ("c", 3)]),
+            &[1, 2, 3],
+        );
+        assert_eq!(
+            plan,
+            MirrorPlan {
+                desired: vec![3, 1, 2],
+                orphans: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn tracks_of_removed_videos_are_orphans() {
+        let plan = plan_mirror(&ids(&["a"]), &ledger(&[("a", 1), ("b", 2)]), &[1, 2]);
+        assert_eq!(
+            plan,
+            MirrorPlan {
+                desired: vec![1],
+                orphans: vec![2],
+            }
+        );
+    }
+
+    #[test]
+    fn duplicate_videos_appear_once() {
+        let plan = plan_mirror(
+            &ids(&["a", "b", "a"]),
+            &ledger(&[("a", 1), ("b", 2)]),
+            &[2, 1],
+        );
+        assert_eq!(plan.desired, vec![1, 2]);
+        assert!(plan.orphans.is_empty());
+    }
+
+    #[test]
+    fn other_tracks_are_kept_at_the_end() {
+        let plan = plan_mirror(
+            &ids(&["b", "a"]),
+            &ledger(&[("a", 1), ("b", 2), ("c", 3)]),
+            &[7, 1, 3, 8, 2],
+        );
+        assert_eq!(
+            plan,
+            MirrorPlan {
+                desired: vec![2, 1, 7, 8],
+                orphans: vec![3],
+            }
+        );
+    }
+
+    #[test]
+    fn reused_and_shared_tracks_are_protected() {
+        let transfer = |youtube: &str, soundcloud: &str, track_id, reused| config::Transfer {
+            youtube_playlist: youtube.to_string(),
+            soundcloud_playlist: soundcloud.to_string(),
+            video_id: "video".to_string(),
+            track_id,
+            title: "title".to_string(),
+            time: "2021-01-01T00:00:00Z".to_string(),
+            reused,
+        };
+        let playlist: config::Playlist =
+            serde_json::from_str(r#"{"youtube": "PLabc", "soundcloud": "url"}"#).unwrap();
+        let transfers = [
+            transfer("PLabc", "url", 1, false),
+            transfer("PLabc", "url", 2, true),
+            transfer("PLabc", "url", 3, false),
+            transfer("PLother", "url", 3, false),
+            transfer("PLabc", "other", 4, false),
+        ];
+        let mut protected: Vec<u64> = protected_tracks(&transfers, &playlist)
+            .into_iter()
+            .collect();
+        protected.sort();
+        assert_eq!(protected, vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn transferred_tracks_missing_from_the_playlist_are_added() {
+        let plan = plan_mirror(&ids(&["a", "b"]), &ledger(&[("a", 1), ("b", 2)]), &[2]);
+        assert_eq!(plan.desired, vec![1, 2]);
     }
 }
@@ -1,39 +1,160 @@
 use crate::util;
 use reqwest::{
-    blocking::{multipart::Form, Client},
+    blocking::{
+        multipart::{Form, Part},
+        Client, Response,
+    },
+    header::AUTHORIZATION,
     StatusCode,
 };
-use serde::Deserialize;
+use serde::{de::DeserializeOwned, Deserialize};
 use std::collections::HashMap;
 
 const SOUNDCLOUD_API_TOKEN: &str = "https://api.soundcloud.com/oauth2/token";
 const SOUNDCLOUD_API_RESOLVE: &str = "https://api.soundcloud.com/resolve.json";
 const SOUNDCLOUD_API_UPLOAD: &str = "https://api.soundcloud.com/tracks";
+const SOUNDCLOUD_API_PLAYLISTS: &str = "https://api.soundcloud.com/playlists";
 const SOUNDCLOUD_API_ME: &str = "https://api.soundcloud.com/me";
+const SOUNDCLOUD_API_MY_TRACKS: &str = "https://api.soundcloud.com/me/tracks";
+const SOUNDCLOUD_API_MY_PLAYLISTS: &str = "https://api.soundcloud.com/me/playlists";
+
+/// The timeout of an upload is this plus the time it takes to send the file at
+/// `MINIMUM_UPLOAD_RATE`.
+const UPLOAD_BASE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(120);
+/// Bytes per second.
+const MINIMUM_UPLOAD_RATE: u64 = 32 * 1024;
 
 #[derive(Debug, Deserialize)]
 pub struct AuthenticateResponse {
     pub access_token: String,
+    /// Seconds until the token expires. Missing for non-expiring tokens.
+    #[serde(default)]
+    pub expires_in: Option<u64>,
 }
 
 #[derive(Debug, Deserialize)]
-pub struct UploadResponse {}
-
-#[derive(Debug, Deserialize)]
-pub struct ResolveResponse {
+struct ResolveResponse {
     location: String,
 }
 
+/// The outcome of resolving a url.
+#[derive(Debug)]
+pub enum Resolved {
+    /// The api url of the resource.
+    Location(String),
+    NotFound,
+    /// The access token was rejected, for example because it expired.
+    Unauthorized,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct PlaylistGetResponse {
-    tracks: Vec<Track>,
+    pub tracks: Vec<Track>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PlaylistResponse {
+    /// The url of the playlist on the website.
+    pub permalink_url: String,
+    /// The api url of the playlist.
+    pub uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PlaylistMetadata {
+    pub title: String,
+    pub description: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Track {
-    id: u64,
+    pub id: u64,
 }
 
+/// A track of the authenticated user.
+#[derive(Debug, Deserialize)]
+pub struct AccountTrack {
+    pub id: u64,
+    pub title: String,
+    /// Space separated tags. Tags containing spaces are quoted.
+    #[serde(default)]
+    pub tag_list: Option<String>,
+}
+
+impl AccountTrack {
+    fn tags(&self) -> Vec<String> {
+        parse_tag_list(self.tag_list.as_deref().unwrap_or_default())
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags().iter().any(|candidate| candidate == tag)
+    }
+
+    /// Whether the track was uploaded from any Youtube video.
+    pub fn has_video_tag(&self) -> bool {
+        let prefix = video_tag("");
+        self.tags().iter().any(|tag| tag.starts_with(&prefix))
+    }
+}
+
+/// A playlist of the authenticated user.
+#[derive(Debug, Deserialize)]
+struct AccountPlaylist {
+    title: String,
+    #[serde(flatten)]
+    playlist: PlaylistResponse,
+}
+
+/// A page of a collection requested with `linked_partitioning`.
+#[derive(Debug, Deserialize)]
+struct Page<T> {
+    collection: Vec<T>,
+    next_href: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Me {
+    quota: Option<Quota>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Quota {
+    #[serde(default)]
+    unlimited_upload_quota: bool,
+    upload_seconds_left: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TrackStatus {
+    /// `processing` while Soundcloud transcodes the upload, then `finished` or `failed`.
+    pub state: String,
+}
+
+fn authorization(access_token: &str) -> String {
+    format!("OAuth {}", access_token)
+}
+
+fn request_token(
+    params: &HashMap<&str, &str>,
+    request_client: &Client,
+) -> Result<Option<AuthenticateResponse>, String> {
+    let response = request_client
+        .post(SOUNDCLOUD_API_TOKEN)
+        .form(params)
+        .send()
+        .map_err(|err| format!("failed to send authenticate request: {}", err))?;
+    match response.status() {
+        StatusCode::UNAUTHORIZED => Ok(None),
+        other if other.is_success() => response
+            .json()
+            .map(Some)
+            .map_err(|err| format!("failed to parse authenticate response: {}", err)),
+        other => Err(format!("response has bad status code: {}", other)),
+    }
+}
+
+/// Obtain a user access token. It is needed for everything that modifies the account like uploads
+/// and playlist changes.
 pub fn authenticate(
     client_id: &str,
     client_secret: &str,
@@ -48,32 +169,27 @@ pub fn authenticate(
     params.insert("password", password);
     params.insert("grant_type", "password");
     params.insert("scope", "non-expiring");
-    let response = request_client
-        .post(SOUNDCLOUD_API_TOKEN)
-        .form(&params)
-        .send()
-        .map_err(|err| format!("failed to send authenticate request: {}", err))?;
-    match response.status() {
-        StatusCode::UNAUTHORIZED => Ok(None),
-        other if other.is_success() => response
-            .json()
-            .map_err(|err| format!("failed to parse authenticate response: {}", err)),
-        other => Err(format!("response has bad status code: {}", other)),
-    }
+    request_token(&params, request_client)
 }
 
-pub fn is_token_valid(
+/// Obtain an application access token which is not tied to a user. It is enough for read only
+/// requests like resolving urls and getting public playlists.
+pub fn authenticate_client_credentials(
     client_id: &str,
-    access_token: &str,
+    client_secret: &str,
     request_client: &Client,
-) -> Result<bool, String> {
-    let url = reqwest::Url::parse_with_params(
-        SOUNDCLOUD_API_ME,
-        &[("client_id", client_id), ("oauth_token", access_token)],
-    )
-    .expect("creation of me url failed");
+) -> Result<Option<AuthenticateResponse>, String> {
+    let mut params = HashMap::new();
+    params.insert("client_id", client_id);
+    params.insert("client_secret", client_secret);
+    params.insert("grant_type", "client_credentials");
+    request_token(&params, request_client)
+}
+
+pub fn is_token_valid(access_token: &str, request_client: &Client) -> Result<bool, String> {
     let response = request_client
-        .get(url)
+        .get(SOUNDCLOUD_API_ME)
+        .header(AUTHORIZATION, authorization(access_token))
         .send()
         .map_err(|err| format!("failed to send resolve request: {}", err))?;
     match response.status() {
@@ -83,102 +199,404 @@ pub fn is_token_valid(
     }
 }
 
-pub fn resolve(
-    url: &str,
-    client_id: &str,
+/// The number of seconds of audio the user can still upload. Returns `None` if the quota is
+/// unlimited or unknown.
+pub fn get_upload_seconds_left(
+    access_token: &str,
     request_client: &Client,
-) -> Result<Option<ResolveResponse>, String> {
-    let url = reqwest::Url::parse_with_params(
-        SOUNDCLOUD_API_RESOLVE,
-        &[("url", url), ("client_id", client_id)],
-    )
-    .expect("creation of resolve url failed");
+) -> Result<Option<u64>, String> {
+    let me: Me = request_client
+        .get(SOUNDCLOUD_API_ME)
+        .header(AUTHORIZATION, authorization(access_token))
+        .send()
+        .map_err(|err| format!("failed to send get user request: {}", err))
+        .and_then(util::handle_status_code)?
+        .json()
+        .map_err(|err| format!("failed to parse get user response: {}", err))?;
+    Ok(me
+        .quota
+        .filter(|quota| !quota.unlimited_upload_quota)
+        .and_then(|quota| quota.upload_seconds_left))
+}
+
+pub fn resolve(url: &str, access_token: &str, request_client: &Client) -> Result<Resolved, String> {
+    let url = reqwest::Url::parse_with_params(SOUNDCLOUD_API_RESOLVE, &[("url", url)])
+        .expect("creation of resolve url failed");
     let response = request_client
         .get(url)
+        .header(AUTHORIZATION, authorization(access_token))
         .send()
         .map_err(|err| format!("failed to send resolve request: {}", err))?;
     match response.status() {
-        StatusCode::NOT_FOUND => Ok(None),
+        StatusCode::NOT_FOUND => Ok(Resolved::NotFound),
+        StatusCode::UNAUTHORIZED => Ok(Resolved::Unauthorized),
         StatusCode::FOUND => response
             .json()
+            .map(|response: ResolveResponse| Resolved::Location(response.location))
             .map_err(|err| format!("failed to parse resolve response: {}", err)),
         other => Err(format!("response has bad status code: {}", other)),
     }
 }
 
-pub fn playlist_url_to_api_url(
+/// Like `util::handle_status_code` but a 404 is not an error.
+fn handle_not_found(response: Response) -> Result<Option<Response>, String> {
+    match response.status() {
+        StatusCode::NOT_FOUND => Ok(None),
+        _ => util::handle_status_code(response).map(Some),
+    }
+}
+
+/// Returns `None` if the playlist does not exist.
+pub fn get_tracks(
+    playlist_api_url: &str,
+    access_token: &str,
+    request_client: &Client,
+) -> Result<Option<PlaylistGetResponse>, String> {
+    let url = reqwest::Url::parse_with_params(playlist_api_url, &[("representation", "id")])
+        .expect("creation of playlist url failed");
+    match request_client
+        .get(url)
+        .header(AUTHORIZATION, authorization(access_token))
+        .send()
+        .map_err(|err| format!("failed to send get tracks request: {}", err))
+        .and_then(handle_not_found)?
+    {
+        Some(response) => response
+            .json()
+            .map(Some)
+            .map_err(|err| format!("failed to parse of get tracks response: {}", err)),
+        None => Ok(None),
+    }
+}
+
+/// The machine tag that identifies the Youtube video a track was uploaded from.
+pub fn video_tag(video_id: &str) -> String {
+    format!("youtube:video={}", video_id)
+}
+
+/// Tags in the `youtube:` namespace are reserved for the tags of this program.
+pub fn is_machine_tag(tag: &str) -> bool {
+    tag.trim().to_lowercase().starts_with("youtube:")
+}
+
+/// The tags of a `tag_list`. A quoted tag is one tag even if it contains spaces.
+pub fn parse_tag_list(tag_list: &str) -> Vec<String> {
+    let mut tags = Vec::new();
+    let mut tag = String::new();
+    let mut quoted = false;
+    for c in tag_list.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !tag.is_empty() {
+                    tags.push(std::mem::take(&mut tag));
+                }
+            }
+            c => tag.push(c),
+        }
+    }
+    if !tag.is_empty() {
+        tags.push(tag);
+    }
+    tags
+}
+
+/// A `tag_list` with the tags in this order. Tags with spaces are quoted and quotes inside tags
+/// are removed.
+pub fn tag_list(tags: &[String]) -> String {
+    tags.iter()
+        .map(|tag| tag.replace('"', ""))
+        .filter(|tag| !tag.trim().is_empty())
+        .map(|tag| {
+            if tag.contains(char::is_whitespace) {
+                format!("\"{}\"", tag)
+            } else {
+                tag
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// All items of a collection of the authenticated user. `name` describes the items for errors.
+fn get_all<T: DeserializeOwned>(
     url: &str,
-    client_id: &str,
+    name: &str,
+    access_token: &str,
     request_client: &Client,
-) -> Result<Option<String>, String> {
-    resolve(url, client_id, request_client)
-        .map(|response| response.map(|response| response.location))
+) -> Result<Vec<T>, String> {
+    let mut items = Vec::new();
+    let mut url =
+        reqwest::Url::parse_with_params(url, &[("linked_partitioning", "true"), ("limit", "200")])
+            .expect("creation of collection url failed")
+            .to_string();
+    loop {
+        let page: Page<T> = request_client
+            .get(&url)
+            .header(AUTHORIZATION, authorization(access_token))
+            .send()
+            .map_err(|err| format!("failed to send get {} request: {}", name, err))
+            .and_then(util::handle_status_code)?
+            .json()
+            .map_err(|err| format!("failed to parse get {} response: {}", name, err))?;
+        items.extend(page.collection);
+        match page.next_href {
+            Some(next) => url = next,
+            None => return Ok(items),
+        }
+    }
 }
 
-pub fn get_tracks(
+/// All tracks of the authenticated user.
+pub fn get_my_tracks(
+    access_token: &str,
+    request_client: &Client,
+) -> Result<Vec<AccountTrack>, String> {
+    get_all(
+        SOUNDCLOUD_API_MY_TRACKS,
+        "tracks",
+        access_token,
+        request_client,
+    )
+}
+
+/// A playlist of the authenticated user with this title.
+pub fn find_my_playlist(
+    title: &str,
+    access_token: &str,
+    request_client: &Client,
+) -> Result<Option<PlaylistResponse>, String> {
+    let playlists: Vec<AccountPlaylist> = get_all(
+        SOUNDCLOUD_API_MY_PLAYLISTS,
+        "playlists",
+        access_token,
+        request_client,
+    )?;
+    Ok(playlists
+        .into_iter()
+        .find(|playlist| playlist.title == title)
+        .map(|playlist| playlist.playlist))
+}
+
+/// Append the tracks that are not in the playlist yet with a single update. Returns the tracks
+/// that the updated playlist does not contain or `None` if the playlist does not exist.
+pub fn add_to_playlist(
+    new_track_ids: &[u64],
     playlist_api_url: &str,
-    client_id: &str,
+    access_token: &str,
     request_client: &Client,
-) -> Result<PlaylistGetResponse, String> {
-    let url = reqwest::Url::parse_with_params(
-        playlist_api_url,
-        &[("client_id", client_id), ("representation", "id")],
+) -> Result<Option<Vec<u64>>, String> {
+    let mut track_ids: Vec<u64> = match get_tracks(playlist_api_url, access_token, request_client)?
+    {
+        Some(response) => response.tracks.iter().map(|track| track.id).collect(),
+        None => return Ok(None),
+    };
+    let missing: Vec<u64> = new_track_ids
+        .iter()
+        .filter(|id| !track_ids.contains(id))
+        .copied()
+        .collect();
+    if missing.is_empty() {
+        return Ok(Some(Vec::new()));
+    }
+    track_ids.extend(missing);
+    let updated = set_tracks(&track_ids, playlist_api_url, access_token, request_client)?;
+    Ok(Some(
+        new_track_ids
+            .iter()
+            .filter(|id| !updated.contains(id))
+            .copied()
+            .collect(),
+    ))
+}
+
+/// The tracks of `track_ids` that are not in the playlist. Returns `None` if the playlist does not
+/// exist.
+pub fn missing_tracks(
+    track_ids: &[u64],
+    playlist_api_url: &str,
+    access_token: &str,
+    request_client: &Client,
+) -> Result<Option<Vec<u64>>, String> {
+    Ok(
+        get_tracks(playlist_api_url, access_token, request_client)?.map(|response| {
+            track_ids
+                .iter()
+                .filter(|id| !response.tracks.iter().any(|track| track.id == **id))
+                .copied()
+                .collect()
+        }),
     )
-    .expect("creation of playlist url failed");
+}
+
+/// Replace the tracks of the playlist with `track_ids` in this order. Returns the ids of the
+/// tracks in the updated playlist.
+pub fn set_tracks(
+    track_ids: &[u64],
+    playlist_api_url: &str,
+    access_token: &str,
+    request_client: &Client,
+) -> Result<Vec<u64>, String> {
+    let mut params = vec![("representation", "id".to_string())];
+    for track_id in track_ids.iter() {
+        params.push(("playlist[tracks][][id]", track_id.to_string()));
+    }
+    let response: PlaylistGetResponse = request_client
+        .put(playlist_api_url)
+        .header(AUTHORIZATION, authorization(access_token))
+        .form(&params)
+        .send()
+        .map_err(|err| format!("failed to send playlist put request: {}", err))
+        .and_then(util::handle_status_code)?
+        .json()
+        .map_err(|err| format!("failed to parse playlist put response: {}", err))?;
+    Ok(response.tracks.iter().map(|track| track.id).collect())
+}
+
+pub fn create_playlist<T: AsRef<std::path::Path>>(
+    title: &str,
+    description: &str,
+    artwork_path: &Option<T>,
+    access_token: &str,
+    request_client: &Client,
+) -> Result<PlaylistResponse, String> {
+    let mut params = Form::new()
+        .text("playlist[title]", title.to_string())
+        .text("playlist[description]", description.to_string())
+        .text("playlist[sharing]", "public");
+    if let Some(artwork_path) = artwork_path {
+        params = params
+            .file("playlist[artwork_data]", artwork_path)
+            .map_err(|err| {
+                format!(
+                    "failed to open artwork file {}: {}",
+                    util::path_to_str(artwork_path),
+                    err
+                )
+            })?;
+    }
     request_client
-        .get(url)
+        .post(SOUNDCLOUD_API_PLAYLISTS)
+        .header(AUTHORIZATION, authorization(access_token))
+        .multipart(params)
         .send()
-        .map_err(|err| format!("failed to send get tracks request: {}", err))
+        .map_err(|err| format!("failed to send create playlist request: {}", err))
         .and_then(util::handle_status_code)?
         .json()
-        .map_err(|err| format!("failed to parse of get tracks response: {}", err))
+        .map_err(|err| format!("failed to parse create playlist response: {}", err))
 }
 
-pub fn add_to_playlist(
-    track_id: u64,
+/// Returns `None` if the playlist does not exist.
+pub fn get_playlist_metadata(
     playlist_api_url: &str,
-    client_id: &str,
+    access_token: &str,
+    request_client: &Client,
+) -> Result<Option<PlaylistMetadata>, String> {
+    match request_client
+        .get(playlist_api_url)
+        .header(AUTHORIZATION, authorization(access_token))
+        .send()
+        .map_err(|err| format!("failed to send get playlist request: {}", err))
+        .and_then(handle_not_found)?
+    {
+        Some(response) => response
+            .json()
+            .map(Some)
+            .map_err(|err| format!("failed to parse get playlist response: {}", err)),
+        None => Ok(None),
+    }
+}
+
+pub fn update_playlist_metadata<T: AsRef<std::path::Path>>(
+    playlist_api_url: &str,
+    title: &str,
+    description: &str,
+    artwork_path: &Option<T>,
     access_token: &str,
     request_client: &Client,
 ) -> Result<(), String> {
-    let previous_tracks = get_tracks(playlist_api_url, client_id, request_client)?.tracks;
-    let track_id = format!("{}", track_id);
-    let mut params = vec![
-        ("client_id", client_id.to_string()),
-        ("oauth_token", access_token.to_string()),
-        ("representation", "compact".to_string()),
-    ];
-    for track in previous_tracks.iter() {
-        params.push(("playlist[tracks][][id]", track.id.to_string()));
-    }
-    params.push(("playlist[tracks][][id]", track_id));
+    let mut params = Form::new()
+        .text("playlist[title]", title.to_string())
+        .text("playlist[description]", description.to_string());
+    if let Some(artwork_path) = artwork_path {
+        params = params
+            .file("playlist[artwork_data]", artwork_path)
+            .map_err(|err| {
+                format!(
+                    "failed to open artwork file {}: {}",
+                    util::path_to_str(artwork_path),
+                    err
+                )
+            })?;
+    }
     request_client
         .put(playlist_api_url)
-        .form(&params)
+        .header(AUTHORIZATION, authorization(access_token))
+        .multipart(params)
         .send()
         .map_err(|err| format!("failed to send playlist put request: {}", err))
         .and_then(util::handle_status_code)
         .map(|_| ())
 }
 
-pub fn upload<T: AsRef<std::path::Path>, U: AsRef<std::path::Path>>(
+/// Returns `None` if the track does not exist.
+pub fn get_track_status(
+    track_id: u64,
+    access_token: &str,
+    request_client: &Client,
+) -> Result<Option<TrackStatus>, String> {
+    match request_client
+        .get(&format!("{}/{}", SOUNDCLOUD_API_UPLOAD, track_id))
+        .header(AUTHORIZATION, authorization(access_token))
+        .send()
+        .map_err(|err| format!("failed to send get track request: {}", err))
+        .and_then(handle_not_found)?
+    {
+        Some(response) => response
+            .json()
+            .map(Some)
+            .map_err(|err| format!("failed to parse get track response: {}", err)),
+        None => Ok(None),
+    }
+}
+
+/// Deleting a track that does not exist succeeds.
+pub fn delete_track(
+    track_id: u64,
+    access_token: &str,
+    request_client: &Client,
+) -> Result<(), String> {
+    let response = request_client
+        .delete(&format!("{}/{}", SOUNDCLOUD_API_UPLOAD, track_id))
+        .header(AUTHORIZATION, authorization(access_token))
+        .send()
+        .map_err(|err| format!("failed to send delete track request: {}", err))?;
+    match response.status() {
+        StatusCode::NOT_FOUND => Ok(()),
+        _ => util::handle_status_code(response).map(|_| ()),
+    }
+}
+
+/// The audio file is streamed and `on_progress` is called with the number of bytes of it sent so
+/// far and its size.
+pub fn upload<T: AsRef<std::path::Path>, U: AsRef<std::path::Path>, F>(
     file_path: T,
     artwork_path: &Option<U>,
     metadata: &HashMap<&str, &str>,
-    client_id: &str,
+    mut on_progress: F,
     access_token: &str,
     request_client: &Client,
-) -> Result<u64, String> {
-    let mut params = Form::new()
-        .text("client_id", client_id.to_string())
-        .text("oauth_token", access_token.to_string());
+) -> Result<u64, String>
+where
+    F: FnMut(u64, u64) + Send + 'static,
+{
+    let mut params = Form::new();
     for (key, value) in metadata {
         params = params.text(format!("track[{}]", key), value.to_string());
     }
     // Not being able to access the specified files is a panic because the caller should have made
     // sure that they exist
-    params = params
-        .file("track[asset_data]", &file_path)
+    let file = std::fs::File::open(&file_path)
         .map_err(|err| {
             format!(
                 "failed to open audio file {}: {}",
@@ -187,6 +605,13 @@ pub fn upload<T: AsRef<std::path::Path>, U: AsRef<std::path::Path>>(
             )
         })
         .unwrap();
+    let size = file.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+    let reader = util::ProgressReader::new(file, move |sent| on_progress(sent, size));
+    let mut part = Part::reader_with_length(reader, size);
+    if let Some(name) = file_path.as_ref().file_name() {
+        part = part.file_name(name.to_string_lossy().into_owned());
+    }
+    params = params.part("track[asset_data]", part);
     if let Some(artwork_path) = artwork_path {
         params = params
             .file("track[artwork_data]", artwork_path)
@@ -199,9 +624,12 @@ pub fn upload<T: AsRef<std::path::Path>, U: AsRef<std::path::Path>>(
             })
             .unwrap();
     }
+    let timeout = UPLOAD_BASE_TIMEOUT + std::time::Duration::from_secs(size / MINIMUM_UPLOAD_RATE);
     let track: Track = request_client
         .post(SOUNDCLOUD_API_UPLOAD)
+        .header(AUTHORIZATION, authorization(access_token))
         .multipart(params)
+        .timeout(timeout)
         .send()
         .map_err(|err| format!("failed to send upload request: {}", err))
         .and_then(util::handle_status_code)?
@@ -209,3 +637,80 @@ pub fn upload<T: AsRef<std::path::Path>, U: AsRef<std::path::Path>>(
         .map_err(|err| format!("failed to parse upload response: {}", err))?;
     Ok(track.id)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(tags: &[&str]) -> Vec<String> {
+        tags.iter().map(|tag| tag.to_string()).collect()
+    }
+
+    fn track(tag_list: &str) -> AccountTrack {
+        AccountTrack {
+            id: 1,
+            title: "title".to_string(),
+            tag_list: Some(tag_list.to_string()),
+        }
+    }
+
+    #[test]
+    fn tags_are_separated_by_spaces() {
+        assert_eq!(
+            tag_list(&tags(&["youtube:video=abc", "rock"])),
+            "youtube:video=abc rock"
+        );
+    }
+
+    #[test]
+    fn tags_with_whitespace_are_quoted() {
+        assert_eq!(
+            tag_list(&tags(&["hard rock", "a\tb", "pop"])),
+            "\"hard rock\" \"a\tb\" pop"
+        );
+    }
+
+    #[test]
+    fn quotes_and_empty_tags_are_removed() {
+        assert_eq!(
+            tag_list(&tags(&["say \"hi\"", "\"\"", "", "  ", "x"])),
+            "\"say hi\" x"
+        );
+        assert_eq!(tag_list(&[]), "");
+    }
+
+    #[test]
+    fn tag_lists_are_parsed_with_quotes() {
+        assert_eq!(
+            parse_tag_list("rock  \"hard rock\" \"live youtube:video=abc\" x"),
+            tags(&["rock", "hard rock", "live youtube:video=abc", "x"])
+        );
+        assert_eq!(parse_tag_list(""), Vec::<String>::new());
+        let tags = tags(&["a b", "c", "youtube:video=abc"]);
+        assert_eq!(parse_tag_list(&tag_list(&tags)), tags);
+    }
+
+    #[test]
+    fn machine_tags_inside_quoted_tags_do_not_match() {
+        let track = track("\"live youtube:video=abc\" rock");
+        assert!(!track.has_tag("youtube:video=abc"));
+        assert!(!track.has_video_tag());
+    }
+
+    #[test]
+    fn machine_tags_are_recognized() {
+        assert!(is_machine_tag("youtube:video=abc"));
+        assert!(is_machine_tag(" YouTube:Video=abc"));
+        assert!(!is_machine_tag("youtube"));
+        assert!(!is_machine_tag("live youtube:video=abc"));
+    }
+
+    #[test]
+    fn tags_are_found_in_the_tag_list() {
+        let track = track(&tag_list(&tags(&["hard rock", "youtube:video=abc"])));
+        assert!(track.has_tag("youtube:video=abc"));
+        assert!(!track.has_tag("youtube:video=ab"));
+        assert!(track.has_video_tag());
+        assert!(!self::track("\"hard rock\" pop").has_video_tag());
+    }
+}
@@ -0,0 +1,558 @@
+use crate::config::{AppToken, Playlist, QuotaUsage, Skip, Transfer};
+use crate::store::{JsonStore, Store};
+use rusqlite::{params, Connection, OptionalExtension};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS playlists (
+        id INTEGER PRIMARY KEY,
+        -- The playlist entry as it would appear in playlists.json.
+        mapping TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS transfers (
+        id INTEGER PRIMARY KEY,
+        youtube_playlist TEXT NOT NULL,
+        soundcloud_playlist TEXT NOT NULL,
+        video_id TEXT NOT NULL,
+        track_id INTEGER NOT NULL,
+        title TEXT NOT NULL,
+        time TEXT NOT NULL,
+        reused INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE INDEX IF NOT EXISTS transfers_video_id ON transfers (video_id);
+    CREATE TABLE IF NOT EXISTS skipped (
+        id INTEGER PRIMARY KEY,
+        youtube_playlist TEXT NOT NULL,
+        video_id TEXT NOT NULL,
+        reason TEXT NOT NULL,
+        time TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS youtube_quota (
+        day TEXT PRIMARY KEY,
+        units INTEGER NOT NULL,
+        exhausted INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE TABLE IF NOT EXISTS tokens (
+        name TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+";
+
+const SOUNDCLOUD_ACCESS_TOKEN: &str = "soundcloud_access_token";
+/// Stored as json.
+const SOUNDCLOUD_APP_TOKEN: &str = "soundcloud_app_token";
+
+/// Stores everything in a SQLite database. Every change is committed immediately.
+pub struct SqliteStore {
+    connection: Connection,
+    /// Row ids of the playlists in the order returned by `playlists`.
+    ids: Vec<i64>,
+    playlists: Vec<Playlist>,
+}
+
+impl SqliteStore {
+    pub fn open(path: &str) -> Result<SqliteStore, String> {
+        make_private(path)?;
+        let connection =
+            Connection::open(path).map_err(|err| format!("failed to open {}: {}", path, err))?;
+        SqliteStore::with_connection(connection).map_err(|err| format!("{}: {}", path, err))
+    }
+
+    /// Create the missing tables and columns in the database of `connection`.
+    fn with_connection(connection: Connection) -> Result<SqliteStore, String> {
+        connection
+            .execute_batch(SCHEMA)
+            .map_err(|err| format!("failed to create tables: {}", err))?;
+        add_missing_columns(&connection)
+            .map_err(|err| format!("failed to upgrade the tables: {}", err))?;
+        let mut store = SqliteStore {
+            connection,
+            ids: Vec::new(),
+            playlists: Vec::new(),
+        };
+        store.load_playlists()?;
+        Ok(store)
+    }
+
+    fn load_playlists(&mut self) -> Result<(), String> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT id, mapping FROM playlists ORDER BY id")
+            .map_err(|err| format!("failed to prepare playlists query: {}", err))?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(|err| format!("failed to query playlists: {}", err))?;
+        self.ids.clear();
+        self.playlists.clear();
+        for row in rows {
+            let (id, mapping) = row.map_err(|err| format!("failed to read playlist: {}", err))?;
+            let mut playlist: Playlist = serde_json::from_str(&mapping)
+                .map_err(|err| format!("failed to parse playlist with id {}: {}", id, err))?;
+            playlist
+                .normalize()
+                .map_err(|err| format!("playlist with id {}: {}", id, err))?;
+            self.ids.push(id);
+            self.playlists.push(playlist);
+        }
+        Ok(())
+    }
+
+    /// Copy everything from the json files. Existing playlists and transfers in the database are
+    /// replaced.
+    pub fn import(&mut self, json: &JsonStore) -> Result<(), String> {
+        let transaction = self
+            .connection
+            .transaction()
+            .map_err(|err| format!("failed to start transaction: {}", err))?;
+        transaction
+            .execute_batch("DELETE FROM playlists; DELETE FROM transfers; DELETE FROM skipped;")
+            .map_err(|err| format!("failed to clear database: {}", err))?;
+        for playlist in json.playlists.playlists.iter() {
+            transaction
+                .execute(
+                    "INSERT INTO playlists (mapping) VALUES (?1)",
+                    params![to_json(playlist)?],
+                )
+                .map_err(|err| format!("failed to insert playlist: {}", err))?;
+        }
+        for transfer in json.state.transfers.iter() {
+            insert_transfer(&transaction, transfer)?;
+        }
+        for skip in json.state.skipped.iter() {
+            insert_skip(&transaction, skip)?;
+        }
+        if let Some(usage) = &json.state.youtube_quota {
+            set_youtube_quota(&transaction, usage)?;
+        }
+        if let Some(access_token) = &json.state.soundcloud_access_token {
+            set_token(&transaction, SOUNDCLOUD_ACCESS_TOKEN, access_token)?;
+        }
+        if let Some(app_token) = &json.state.soundcloud_app_token {
+            set_token(
+                &transaction,
+                SOUNDCLOUD_APP_TOKEN,
+                &app_token_to_json(app_token)?,
+            )?;
+        }
+        transaction
+            .commit()
+            .map_err(|err| format!("failed to commit transaction: {}", err))?;
+        self.load_playlists()
+    }
+}
+
+impl Store for SqliteStore {
+    fn playlists(&self) -> Vec<Playlist> {
+        self.playlists.clone()
+    }
+
+    fn update_playlist(&mut self, index: usize, playlist: &Playlist) -> Result<(), String> {
+        update_playlist(&self.connection, self.ids[index], playlist)?;
+        self.playlists[index] = playlist.clone();
+        Ok(())
+    }
+
+    fn access_token(&self) -> Option<String> {
+        get_token(&self.connection, SOUNDCLOUD_ACCESS_TOKEN)
+    }
+
+    fn set_access_token(&mut self, access_token: &str) -> Result<(), String> {
+        set_token(&self.connection, SOUNDCLOUD_ACCESS_TOKEN, access_token)
+    }
+
+    /// A token that cannot be parsed is ignored so that a new one is requested.
+    fn app_token(&self) -> Option<AppToken> {
+        get_token(&self.connection, SOUNDCLOUD_APP_TOKEN)
+            .and_then(|json| serde_json::from_str(&json).ok())
+    }
+
+    fn set_app_token(&mut self, token: &AppToken) -> Result<(), String> {
+        set_token(
+            &self.connection,
+            SOUNDCLOUD_APP_TOKEN,
+            &app_token_to_json(token)?,
+        )
+    }
+
+    fn record_transfer(
+        &mut self,
+        index: usize,
+        playlist: &Playlist,
+        transfer: &Transfer,
+    ) -> Result<(), String> {
+        let transaction = self
+            .connection
+            .transaction()
+            .map_err(|err| format!("failed to start transaction: {}", err))?;
+        insert_transfer(&transaction, transfer)?;
+        update_playlist(&transaction, self.ids[index], playlist)?;
+        transaction
+            .commit()
+            .map_err(|err| format!("failed to commit transaction: {}", err))?;
+        self.playlists[index] = playlist.clone();
+        Ok(())
+    }
+
+    fn transfers(&self) -> Result<Vec<Transfer>, String> {
+        let mut statement = self
+            .connection
+            .prepare(
+                "SELECT youtube_playlist, soundcloud_playlist, video_id, track_id, title, time, \
+                 reused FROM transfers ORDER BY id",
+            )
+            .map_err(|err| format!("failed to prepare transfers query: {}", err))?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok(Transfer {
+                    youtube_playlist: row.get(0)?,
+                    soundcloud_playlist: row.get(1)?,
+                    video_id: row.get(2)?,
+                    track_id: row.get::<_, i64>(3)? as u64,
+                    title: row.get(4)?,
+                    time: row.get(5)?,
+                    reused: row.get(6)?,
+                })
+            })
+            .map_err(|err| format!("failed to query transfers: {}", err))?;
+        rows.collect::<Result<_, _>>()
+            .map_err(|err| format!("failed to read transfer: {}", err))
+    }
+
+    fn record_skip(&mut self, skip: &Skip) -> Result<(), String> {
+        insert_skip(&self.connection, skip)
+    }
+
+    fn skipped(&self) -> Result<Vec<Skip>, String> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT youtube_playlist, video_id, reason, time FROM skipped ORDER BY id")
+            .map_err(|err| format!("failed to prepare skipped videos query: {}", err))?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok(Skip {
+                    youtube_playlist: row.get(0)?,
+                    video_id: row.get(1)?,
+                    reason: row.get(2)?,
+                    time: row.get(3)?,
+                })
+            })
+            .map_err(|err| format!("failed to query skipped videos: {}", err))?;
+        rows.collect::<Result<_, _>>()
+            .map_err(|err| format!("failed to read skipped video: {}", err))
+    }
+
+    fn youtube_quota(&self, day: &str) -> QuotaUsage {
+        let (units, exhausted) = self
+            .connection
+            .query_row(
+                "SELECT units, exhausted FROM youtube_quota WHERE day = ?1",
+                params![day],
+                |row| Ok((row.get::<_, i64>(0)?, row.get::<_, bool>(1)?)),
+            )
+            .optional()
+            .unwrap_or_else(|err| {
+                println!("Error: failed to read Youtube quota usage: {}", err);
+                None
+            })
+            .unwrap_or((0, false));
+        QuotaUsage {
+            day: day.to_string(),
+            units: units as u64,
+            exhausted,
+        }
+    }
+
+    fn set_youtube_quota(&mut self, usage: &QuotaUsage) -> Result<(), String> {
+        set_youtube_quota(&self.connection, usage)
+    }
+}
+
+fn to_json(playlist: &Playlist) -> Result<String, String> {
+    serde_json::to_string(playlist).map_err(|err| format!("failed to serialize playlist: {}", err))
+}
+
+/// The database holds the access token so it is only accessible by the current user. SQLite
+/// gives its journal files the same permissions.
+#[cfg(unix)]
+fn make_private(path: &str) -> Result<(), String> {
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .mode(0o600)
+        .open(path)
+        .and_then(|file| file.set_permissions(std::fs::Permissions::from_mode(0o600)))
+        .map_err(|err| format!("failed to restrict permissions of {}: {}", path, err))
+}
+
+#[cfg(not(unix))]
+fn make_private(_path: &str) -> Result<(), String> {
+    Ok(())
+}
+
+fn update_playlist(connection: &Connection, id: i64, playlist: &Playlist) -> Result<(), String> {
+    connection
+        .execute(
+            "UPDATE playlists SET mapping = ?1 WHERE id = ?2",
+            params![to_json(playlist)?, id],
+        )
+        .map(|_| ())
+        .map_err(|err| format!("failed to update playlist: {}", err))
+}
+
+fn app_token_to_json(token: &AppToken) -> Result<String, String> {
+    serde_json::to_string(token).map_err(|err| format!("failed to serialize token: {}", err))
+}
+
+fn get_token(connection: &Connection, name: &str) -> Option<String> {
+    connection
+        .query_row(
+            "SELECT value FROM tokens WHERE name = ?1",
+            params![name],
+            |row| row.get(0),
+        )
+        .optional()
+        .unwrap_or_else(|err| {
+            println!("Error: failed to read {}: {}", name, err);
+            None
+        })
+}
+
+fn set_token(connection: &Connection, name: &str, value: &str) -> Result<(), String> {
+    connection
+        .execute(
+            "INSERT OR REPLACE INTO tokens (name, value) VALUES (?1, ?2)",
+            params![name, value],
+        )
+        .map(|_| ())
+        .map_err(|err| format!("failed to store token: {}", err))
+}
+
+fn insert_transfer(connection: &Connection, transfer: &Transfer) -> Result<(), String> {
+    connection
+        .execute(
+            "INSERT INTO transfers \
+             (youtube_playlist, soundcloud_playlist, video_id, track_id, title, time, reused) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                transfer.youtube_playlist,
+                transfer.soundcloud_playlist,
+                transfer.video_id,
+                transfer.track_id as i64,
+                transfer.title,
+                transfer.time,
+                transfer.reused,
+            ],
+        )
+        .map(|_| ())
+        .map_err(|err| format!("failed to record transfer: {}", err))
+}
+
+fn insert_skip(connection: &Connection, skip: &Skip) -> Result<(), String> {
+    connection
+        .execute(
+            "INSERT INTO skipped (youtube_playlist, video_id, reason, time) \
+             VALUES (?1, ?2, ?3, ?4)",
+            params![skip.youtube_playlist, skip.video_id, skip.reason, skip.time],
+        )
+        .map(|_| ())
+        .map_err(|err| format!("failed to insert skipped video: {}", err))
+}
+
+/// Add the columns that were added to `SCHEMA` after the tables were created.
+fn add_missing_columns(connection: &Connection) -> rusqlite::Result<()> {
+    let columns = [
+        ("youtube_quota", "exhausted", "INTEGER NOT NULL DEFAULT 0"),
+        ("transfers", "reused", "INTEGER NOT NULL DEFAULT 0"),
+    ];
+    for (table, column, definition) in columns {
+        let exists = connection
+            .prepare("SELECT 1 FROM pragma_table_info(?1) WHERE name = ?2")?
+            .exists(params![table, column])?;
+        if !exists {
+            connection.execute_batch(&format!(
+                "ALTER TABLE {} ADD COLUMN {} {}",
+                table, column, definition
+            ))?;
+        }
+    }
+    Ok(())
+}
+
+fn set_youtube_quota(connection: &Connection, usage: &QuotaUsage) -> Result<(), String> {
+    connection
+        .execute(
+            "INSERT OR REPLACE INTO youtube_quota (day, units, exhausted) VALUES (?1, ?2, ?3)",
+            params![usage.day, usage.units as i64, usage.exhausted],
+        )
+        .map(|_| ())
+        .map_err(|err| format!("failed to store Youtube quota usage: {}", err))
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::config::{Playlists, State};
+
+    fn store() -> SqliteStore {
+        SqliteStore::with_connection(Connection::open_in_memory().unwrap()).unwrap()
+    }
+
+    fn playlists(positions: &[u64]) -> Playlists {
+        let playlists: Vec<serde_json::Value> = positions
+            .iter()
+            .map(|position| {
+                serde_json::json!({"youtube": "PLabc", "soundcloud": "url", "position": position})
+            })
+            .collect();
+        serde_json::from_value(serde_json::json!({ "playlists": playlists })).unwrap()
+    }
+
+    fn transfer(video_id: &str) -> Transfer {
+        Transfer {
+            youtube_playlist: "PLabc".to_string(),
+            soundcloud_playlist: "url".to_string(),
+            video_id: video_id.to_string(),
+            track_id: 1,
+            title: "title".to_string(),
+            time: "2021-01-01T00:00:00Z".to_string(),
+            reused: false,
+        }
+    }
+
+    fn skip(video_id: &str) -> Skip {
+        Skip {
+            youtube_playlist: "PLabc".to_string(),
+            video_id: video_id.to_string(),
+            reason: "reason".to_string(),
+            time: "2021-01-01T00:00:00Z".to_string(),
+        }
+    }
+
+    fn video_ids(transfers: Vec<Transfer>) -> Vec<String> {
+        transfers
+            .into_iter()
+            .map(|transfer| transfer.video_id)
+            .collect()
+    }
+
+    #[test]
+    fn import_replaces_the_contents() {
+        let mut store = store();
+        store
+            .import(&JsonStore {
+                playlists: playlists(&[1]),
+                state: State {
+                    transfers: vec![transfer("old")],
+                    skipped: vec![skip("old")],
+                    ..Default::default()
+                },
+            })
+            .unwrap();
+        store
+            .import(&JsonStore {
+                playlists: playlists(&[2, 3]),
+                state: State {
+                    soundcloud_access_token: Some("token".to_string()),
+                    soundcloud_app_token: Some(AppToken {
+                        access_token: "app".to_string(),
+                        expires_at: "2021-01-01T00:00:00Z".to_string(),
+                    }),
+                    transfers: vec![transfer("a"), transfer("b")],
+                    skipped: vec![skip("c")],
+                    youtube_quota: Some(QuotaUsage {
+                        day: "2021-01-01".to_string(),
+                        units: 5,
+                        exhausted: true,
+                    }),
+                    ..Default::default()
+                },
+            })
+            .unwrap();
+        let positions: Vec<u64> = store
+            .playlists()
+            .iter()
+            .map(|playlist| playlist.position)
+            .collect();
+        assert_eq!(positions, vec![2, 3]);
+        assert_eq!(video_ids(store.transfers().unwrap()), vec!["a", "b"]);
+        let skipped: Vec<String> = store
+            .skipped()
+            .unwrap()
+            .into_iter()
+            .map(|skip| skip.video_id)
+            .collect();
+        assert_eq!(skipped, vec!["c"]);
+        assert_eq!(store.access_token(), Some("token".to_string()));
+        assert_eq!(store.app_token().unwrap().access_token, "app");
+        let usage = store.youtube_quota("2021-01-01");
+        assert_eq!((usage.units, usage.exhausted), (5, true));
+    }
+
+    #[test]
+    fn transfer_is_recorded_with_the_playlist() {
+        let mut store = store();
+        store
+            .import(&JsonStore {
+                playlists: playlists(&[0]),
+                state: State::default(),
+            })
+            .unwrap();
+        let mut playlist = store.playlists().remove(0);
+        playlist.position = 1;
+        store.record_transfer(0, &playlist, &transfer("a")).unwrap();
+        store.load_playlists().unwrap();
+        assert_eq!(store.playlists()[0].position, 1);
+        assert_eq!(video_ids(store.transfers().unwrap()), vec!["a"]);
+    }
+
+    #[test]
+    fn failed_playlist_update_discards_the_transfer() {
+        let mut store = store();
+        store
+            .import(&JsonStore {
+                playlists: playlists(&[0]),
+                state: State::default(),
+            })
+            .unwrap();
+        store
+            .connection
+            .execute_batch(
+                "CREATE TRIGGER fail BEFORE UPDATE ON playlists \
+                 BEGIN SELECT RAISE(ABORT, 'update failed'); END;",
+            )
+            .unwrap();
+        let mut playlist = store.playlists().remove(0);
+        playlist.position = 1;
+        assert!(store.record_transfer(0, &playlist, &transfer("a")).is_err());
+        assert!(store.transfers().unwrap().is_empty());
+        assert_eq!(store.playlists()[0].position, 0);
+    }
+
+    #[test]
+    fn missing_columns_are_added_to_existing_tables() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE youtube_quota (day TEXT PRIMARY KEY, units INTEGER NOT NULL); \
+                 INSERT INTO youtube_quota VALUES ('2021-01-01', 5); \
+                 CREATE TABLE transfers (id INTEGER PRIMARY KEY, \
+                 youtube_playlist TEXT NOT NULL, soundcloud_playlist TEXT NOT NULL, \
+                 video_id TEXT NOT NULL, track_id INTEGER NOT NULL, title TEXT NOT NULL, \
+                 time TEXT NOT NULL); \
+                 INSERT INTO transfers VALUES (1, 'PLabc', 'url', 'a', 1, 'title', 'time');",
+            )
+            .unwrap();
+        let mut store = SqliteStore::with_connection(connection).unwrap();
+        assert!(!store.transfers().unwrap()[0].reused);
+        let mut usage = store.youtube_quota("2021-01-01");
+        assert_eq!((usage.units, usage.exhausted), (5, false));
+        usage.exhausted = true;
+        store.set_youtube_quota(&usage).unwrap();
+        assert!(store.youtube_quota("2021-01-01").exhausted);
+        // Opening an upgraded database again does not fail.
+        add_missing_columns(&store.connection).unwrap();
+    }
+}
@@ -0,0 +1,148 @@
+use crate::config::{
+    self, AppToken, Config, Playlist, Playlists, QuotaUsage, Skip, State, StoreKind, Transfer,
+};
+
+/// Persistent data that changes while the program runs. Playlists are addressed by their index
+/// in the list returned by `playlists`.
+pub trait Store {
+    fn playlists(&self) -> Vec<Playlist>;
+    fn update_playlist(&mut self, index: usize, playlist: &Playlist) -> Result<(), String>;
+    fn access_token(&self) -> Option<String>;
+    fn set_access_token(&mut self, access_token: &str) -> Result<(), String>;
+    fn app_token(&self) -> Option<AppToken>;
+    fn set_app_token(&mut self, token: &AppToken) -> Result<(), String>;
+    /// Record the transfer together with the playlist at `index`, whose position was moved past
+    /// the video.
+    fn record_transfer(
+        &mut self,
+        index: usize,
+        playlist: &Playlist,
+        transfer: &Transfer,
+    ) -> Result<(), String>;
+    /// All transfers from oldest to newest.
+    fn transfers(&self) -> Result<Vec<Transfer>, String>;
+    fn record_skip(&mut self, skip: &Skip) -> Result<(), String>;
+    /// All skipped videos from oldest to newest.
+    fn skipped(&self) -> Result<Vec<Skip>, String>;
+    /// Youtube api quota usage on `day`.
+    fn youtube_quota(&self, day: &str) -> QuotaUsage;
+    fn set_youtube_quota(&mut self, usage: &QuotaUsage) -> Result<(), String>;
+}
+
+pub fn open(config: &Config) -> Result<Box<dyn Store>, String> {
+    let mut store: Box<dyn Store> = match config.store {
+        StoreKind::Json => Box::new(JsonStore::open()?),
+        #[cfg(feature = "sqlite")]
+        StoreKind::Sqlite => Box::new(crate::sqlite::SqliteStore::open(sqlite_path(config))?),
+        #[cfg(not(feature = "sqlite"))]
+        StoreKind::Sqlite => {
+            return Err(format!(
+                "the sqlite store {} is configured but this build does not support it. \
+                 Rebuild with --features sqlite.",
+                sqlite_path(config)
+            ))
+        }
+    };
+    adopt_legacy_access_token(store.as_mut(), config)?;
+    Ok(store)
+}
+
+/// Move the access token of an old config file into the store unless it already has one.
+pub fn adopt_legacy_access_token(store: &mut dyn Store, config: &Config) -> Result<(), String> {
+    match &config.legacy_access_token {
+        Some(access_token) if store.access_token().is_none() => {
+            store.set_access_token(access_token)
+        }
+        _ => Ok(()),
+    }
+}
+
+pub fn sqlite_path(config: &Config) -> &str {
+    config.sqlite_file.as_deref().unwrap_or(config::SQLITE_FILE)
+}
+
+/// Stores the playlists in `playlists.json` and everything else in `state.json`.
+pub struct JsonStore {
+    pub playlists: Playlists,
+    pub state: State,
+}
+
+impl JsonStore {
+    pub fn open() -> Result<JsonStore, String> {
+        Ok(JsonStore {
+            playlists: Playlists::read()?,
+            state: State::read()?,
+        })
+    }
+}
+
+impl Store for JsonStore {
+    fn playlists(&self) -> Vec<Playlist> {
+        self.playlists.playlists.clone()
+    }
+
+    fn update_playlist(&mut self, index: usize, playlist: &Playlist) -> Result<(), String> {
+        self.playlists.playlists[index] = playlist.clone();
+        self.playlists.write_safe()
+    }
+
+    fn access_token(&self) -> Option<String> {
+        self.state.soundcloud_access_token.clone()
+    }
+
+    fn set_access_token(&mut self, access_token: &str) -> Result<(), String> {
+        self.state.soundcloud_access_token = Some(access_token.to_string());
+        self.state.write_safe()
+    }
+
+    fn app_token(&self) -> Option<AppToken> {
+        self.state.soundcloud_app_token.clone()
+    }
+
+    fn set_app_token(&mut self, token: &AppToken) -> Result<(), String> {
+        self.state.soundcloud_app_token = Some(token.clone());
+        self.state.write_safe()
+    }
+
+    /// The transfer is written first. After a crash in between, the track is found by its tag
+    /// instead of being uploaded again.
+    fn record_transfer(
+        &mut self,
+        index: usize,
+        playlist: &Playlist,
+        transfer: &Transfer,
+    ) -> Result<(), String> {
+        self.state.transfers.push(transfer.clone());
+        self.state.write_safe()?;
+        self.update_playlist(index, playlist)
+    }
+
+    fn transfers(&self) -> Result<Vec<Transfer>, String> {
+        Ok(self.state.transfers.clone())
+    }
+
+    fn record_skip(&mut self, skip: &Skip) -> Result<(), String> {
+        self.state.skipped.push(skip.clone());
+        self.state.write_safe()
+    }
+
+    fn skipped(&self) -> Result<Vec<Skip>, String> {
+        Ok(self.state.skipped.clone())
+    }
+
+    fn youtube_quota(&self, day: &str) -> QuotaUsage {
+        match &self.state.youtube_quota {
+            Some(usage) if usage.day == day => usage.clone(),
+            _ => QuotaUsage {
+                day: day.to_string(),
+                units: 0,
+                exhausted: false,
+            },
+        }
+    }
+
+    fn set_youtube_quota(&mut self, usage: &QuotaUsage) -> Result<(), String> {
+        self.state.youtube_quota = Some(usage.clone());
+        self.state.write_without_backup()
+    }
+}
@@ -4,6 +4,160 @@ pub fn path_to_str<T: AsRef<std::path::Path>>(path: T) -> String {
     path.as_ref().to_string_lossy().into_owned()
 }
 
+/// Replace the file at `path` with `contents` so that after a crash it holds either the old or
+/// the new contents. If `private` is set the file is only accessible by the current user,
+/// otherwise it keeps the permissions of the file it replaces.
+pub fn write_atomic<T: AsRef<std::path::Path>>(
+    path: T,
+    contents: &[u8],
+    private: bool,
+) -> Result<(), String> {
+    use std::io::Write;
+    let path = path.as_ref();
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = std::path::PathBuf::from(temporary);
+
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&temporary)
+        .map_err(|err| format!("failed to open {}: {}", path_to_str(&temporary), err))?;
+    // Set explicitly because a temporary file can be left behind by a crash with other
+    // permissions and the rename drops those of the replaced file.
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = if private {
+            Some(0o600)
+        } else {
+            std::fs::metadata(path)
+                .ok()
+                .map(|metadata| metadata.permissions().mode())
+        };
+        if let Some(mode) = mode {
+            file.set_permissions(std::fs::Permissions::from_mode(mode))
+                .map_err(|err| {
+                    format!(
+                        "failed to set permissions of {}: {}",
+                        path_to_str(&temporary),
+                        err
+                    )
+                })?;
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = private;
+    file.write_all(contents)
+        .and_then(|_| file.sync_all())
+        .map_err(|err| format!("failed to write to {}: {}", path_to_str(&temporary), err))?;
+    drop(file);
+    std::fs::rename(&temporary, path).map_err(|err| {
+        format!(
+            "failed to rename {} to {}: {}",
+            path_to_str(&temporary),
+            path_to_str(path),
+            err
+        )
+    })?;
+    // Make the rename itself durable.
+    #[cfg(unix)]
+    {
+        let directory = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => std::path::Path::new("."),
+        };
+        if let Ok(directory) = std::fs::File::open(directory) {
+            let _ = directory.sync_all();
+        }
+    }
+    Ok(())
+}
+
+/// Calls `on_progress` with the number of bytes read so far after every read.
+pub struct ProgressReader<R, F> {
+    inner: R,
+    read: u64,
+    on_progress: F,
+}
+
+impl<R, F: FnMut(u64)> ProgressReader<R, F> {
+    pub fn new(inner: R, on_progress: F) -> ProgressReader<R, F> {
+        ProgressReader {
+            inner,
+            read: 0,
+            on_progress,
+        }
+    }
+}
+
+impl<R: std::io::Read, F: FnMut(u64)> std::io::Read for ProgressReader<R, F> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        self.read += count as u64;
+        (self.on_progress)(self.read);
+        Ok(count)
+    }
+}
+
+/// Prints the progress of a transfer with its rate and the estimated remaining time at most
+/// every `PROGRESS_INTERVAL`.
+pub struct ProgressPrinter {
+    total: u64,
+    start: std::time::Instant,
+    last_print: std::time::Instant,
+    finished: bool,
+}
+
+const PROGRESS_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+const MEBIBYTE: f64 = 1024.0 * 1024.0;
+
+impl ProgressPrinter {
+    pub fn new(total: u64) -> ProgressPrinter {
+        let now = std::time::Instant::now();
+        ProgressPrinter {
+            total,
+            start: now,
+            last_print: now,
+            finished: false,
+        }
+    }
+
+    /// The last update is always printed.
+    pub fn update(&mut self, done: u64) {
+        let now = std::time::Instant::now();
+        let finished = done >= self.total;
+        if self.finished || (!finished && now - self.last_print < PROGRESS_INTERVAL) {
+            return;
+        }
+        self.last_print = now;
+        self.finished = finished;
+        let elapsed = (now - self.start).as_secs_f64();
+        let rate = if elapsed > 0.0 {
+            done as f64 / elapsed
+        } else {
+            0.0
+        };
+        let remaining = if rate > 0.0 {
+            format!(
+                "{:.0}s remaining",
+                (self.total.saturating_sub(done) as f64 / rate).ceil()
+            )
+        } else {
+            "unknown time remaining".to_string()
+        };
+        println!(
+            "Sent {:.1} of {:.1} MiB ({}%) at {:.2} MiB/s, {}.",
+            done as f64 / MEBIBYTE,
+            self.total as f64 / MEBIBYTE,
+            (done * 100).checked_div(self.total).unwrap_or(100),
+            rate / MEBIBYTE,
+            remaining
+        );
+    }
+}
+
 pub fn handle_status_code(response: Response) -> Result<Response, String> {
     if response.status().is_success() {
         Ok(response)
@@ -29,6 +183,7 @@ pub fn download_file<T: AsRef<std::path::Path>>(
             let mut file = std::fs::OpenOptions::new()
                 .write(true)
                 .create(true)
+                .truncate(true)
                 .open(path.as_ref())
                 .map_err(|err| {
                     format!(
@@ -49,3 +204,44 @@ pub fn download_file<T: AsRef<std::path::Path>>(
                 .map(|_| ())
         })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "youtube_to_soundcloud-{}-{}",
+            std::process::id(),
+            name
+        ));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn contents_are_replaced_without_leaving_the_temporary_file() {
+        let path = path("replace.json");
+        write_atomic(&path, b"old", false).unwrap();
+        write_atomic(&path, b"new", false).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"new");
+        let mut temporary = path.into_os_string();
+        temporary.push(".tmp");
+        assert!(!std::path::Path::new(&temporary).exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn permissions_are_kept_or_made_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let mode =
+            |path: &std::path::Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        let path = path("permissions.json");
+        std::fs::write(&path, b"old").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o640)).unwrap();
+        write_atomic(&path, b"new", false).unwrap();
+        assert_eq!(mode(&path), 0o640);
+        write_atomic(&path, b"newer", true).unwrap();
+        assert_eq!(mode(&path), 0o600);
+    }
+}
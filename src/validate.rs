@@ -0,0 +1,387 @@
+use crate::config::{self, Config, Credentials, Playlist, Playlists, State, StoreKind};
+use crate::format::Format;
+use serde::de::DeserializeOwned;
+use serde_json::value::RawValue;
+use std::collections::BTreeMap;
+
+/// A schema error at a byte offset into the file.
+struct Error {
+    offset: usize,
+    /// The top level field of the checked object the error belongs to.
+    field: Option<String>,
+    message: String,
+}
+
+/// Check all configuration files and print every error instead of stopping at the first one.
+/// Files with an older version are checked as they will be after the upgrade on the next run.
+/// Nothing is written. For toml and yaml files only the first error is reported.
+pub fn validate() -> Result<(), String> {
+    let mut count = 0;
+
+    let config_path = config::config_path();
+    let (config, errors) = check_file::<Config>(&config_path, config::CONFIG_MIGRATIONS, |text| {
+        Ok(object_errors::<Config>(text, root(text)?))
+    })?;
+    count += errors;
+
+    let mut config: Option<Config> = config.and_then(|value| serde_json::from_value(value).ok());
+    if let Some(secrets_path) = config.as_ref().and_then(Config::secrets_path) {
+        count += check_file::<Credentials>(&secrets_path, &[], |text| {
+            Ok(object_errors::<Credentials>(text, root(text)?))
+        })?
+        .1;
+    }
+
+    let playlists_path = config::playlists_path();
+    let store = config.as_ref().map(|config| config.store);
+    if store == Some(StoreKind::Json) || std::path::Path::new(&playlists_path).exists() {
+        let (playlists, errors) = check_file::<Playlists>(
+            &playlists_path,
+            config::PLAYLISTS_MIGRATIONS,
+            playlists_errors,
+        )?;
+        count += errors;
+        // Youtube urls and ids are only checked if the schema is right.
+        if let Some(Ok(playlists)) = playlists
+            .filter(|_| errors == 0)
+            .map(serde_json::from_value::<Playlists>)
+        {
+            for (index, mut playlist) in playlists.playlists.into_iter().enumerate() {
+                if let Err(err) = playlist.normalize() {
+                    println!("{}: playlist {}: {}", playlists_path, index + 1, err);
+                    count += 1;
+                }
+            }
+        }
+    }
+
+    if std::path::Path::new(config::STATE_FILE).exists() {
+        count += check_file::<State>(config::STATE_FILE, config::STATE_MIGRATIONS, |text| {
+            Ok(object_errors::<State>(text, root(text)?))
+        })?
+        .1;
+    }
+
+    // Problems that only show up when combining the files like missing credentials.
+    if let (0, Some(config)) = (count, config.as_mut()) {
+        if let Err(err) = config.complete() {
+            println!("{}: {}", config_path, err);
+            count += 1;
+        }
+    }
+
+    if count == 0 {
+        println!("No errors found.");
+        Ok(())
+    } else {
+        Err(format!("found {} errors", count))
+    }
+}
+
+/// Print the errors of one file. `json_errors` finds all errors of a json file. Returns the
+/// upgraded content if there are no syntax errors and the number of errors.
+fn check_file<T: DeserializeOwned>(
+    path: &str,
+    migrations: &[config::Migration],
+    json_errors: impl Fn(&str) -> Result<Vec<Error>, serde_json::Error>,
+) -> Result<(Option<serde_json::Value>, usize), String> {
+    let format = Format::of(path);
+    let text =
+        std::fs::read_to_string(path).map_err(|err| format!("failed to open {}: {}", path, err))?;
+    let mut value: serde_json::Value = match format.deserialize(&text) {
+        Ok(value) => value,
+        Err(err) => {
+            println!("{}: {}", path, err);
+            return Ok((None, 1));
+        }
+    };
+    let upgraded = if migrations.is_empty() {
+        false
+    } else {
+        match config::migrate(path, &mut value, migrations) {
+            Ok(upgraded) => upgraded,
+            Err(err) => {
+                println!("{}: {}", path, err);
+                return Ok((None, 1));
+            }
+        }
+    };
+    if upgraded {
+        println!(
+            "{}: will be upgraded to version {} on the next run. \
+             Positions refer to the upgraded file.",
+            path,
+            migrations.len()
+        );
+    }
+    if format != Format::Json {
+        let result = if upgraded {
+            serde_json::from_value::<T>(value.clone()).map_err(|err| err.to_string())
+        } else {
+            format.deserialize::<T>(&text)
+        };
+        return match result {
+            Ok(_) => Ok((Some(value), 0)),
+            Err(err) => {
+                println!("{}: {}", path, err);
+                Ok((Some(value), 1))
+            }
+        };
+    }
+    let text = if upgraded {
+        serde_json::to_string_pretty(&value)
+            .map_err(|err| format!("failed to serialize {}: {}", path, err))?
+    } else {
+        text
+    };
+    let mut errors =
+        json_errors(&text).map_err(|err| format!("failed to parse {}: {}", path, err))?;
+    errors.sort_by_key(|error| error.offset);
+    for error in errors.iter() {
+        let (line, column) = line_column(&text, error.offset);
+        println!("{}:{}:{}: {}", path, line, column, error.message);
+    }
+    Ok((Some(value), errors.len()))
+}
+
+fn root(text: &str) -> Result<&RawValue, serde_json::Error> {
+    serde_json::from_str(text)
+}
+
+/// Check every playlist entry separately so that an error in one does not hide the others.
+fn playlists_errors(text: &str) -> Result<Vec<Error>, serde_json::Error> {
+    let root = root(text)?;
+    let mut errors = Vec::new();
+    let entries = serde_json::from_str::<BTreeMap<String, &RawValue>>(root.get())
+        .ok()
+        .and_then(|fields| fields.get("playlists").copied())
+        .and_then(|playlists| serde_json::from_str::<Vec<&RawValue>>(playlists.get()).ok());
+    if let Some(entries) = &entries {
+        for entry in entries.iter() {
+            errors.extend(object_errors::<Playlist>(text, entry));
+        }
+    }
+    errors.extend(
+        object_errors::<Playlists>(text, root)
+            .into_iter()
+            .filter(|error| entries.is_none() || error.field.as_deref() != Some("playlists")),
+    );
+    Ok(errors)
+}
+
+/// Values tried in place of a field with an error so that deserialization can continue.
+const PLACEHOLDERS: &[&str] = &["\"\"", "0", "false", "[]", "{}", "null"];
+
+/// Collect all errors of deserializing a `T` from `object`. Serde stops at the first error so
+/// the field causing it is replaced with a placeholder of a fitting type and deserialization is
+/// repeated until it succeeds.
+fn object_errors<T: DeserializeOwned>(text: &str, object: &RawValue) -> Vec<Error> {
+    let object_offset = offset(text, object);
+    let fields = match serde_json::from_str::<BTreeMap<String, &RawValue>>(object.get()) {
+        Ok(fields) => fields,
+        Err(_) => {
+            return match serde_json::from_str::<T>(object.get()) {
+                Ok(_) => Vec::new(),
+                Err(err) => vec![Error {
+                    offset: object_offset + line_column_to_offset(object.get(), &err),
+                    field: None,
+                    message: message(&err),
+                }],
+            }
+        }
+    };
+    // (key, value, offset of the value in `text` if it is not a placeholder)
+    let mut fields: Vec<(String, String, Option<usize>)> = fields
+        .into_iter()
+        .map(|(key, value)| (key, value.get().to_string(), Some(offset(text, value))))
+        .collect();
+    fields.sort_by_key(|(_, _, offset)| *offset);
+
+    let mut errors = Vec::new();
+    // The field currently being replaced and the index of the next placeholder to try.
+    let mut replacing: Option<(usize, usize)> = None;
+    // Every iteration either tries another placeholder or finds a new error.
+    for _ in 0..(fields.len() + 16) * (PLACEHOLDERS.len() + 1) {
+        // (start of the field, start of the value) in `rebuilt`
+        let mut segments = Vec::new();
+        let mut rebuilt = String::from("{");
+        for (index, (key, value, _)) in fields.iter().enumerate() {
+            if index > 0 {
+                rebuilt.push(',');
+            }
+            let start = rebuilt.len();
+            rebuilt.push_str(&serde_json::to_string(key).unwrap());
+            rebuilt.push(':');
+            segments.push((start, rebuilt.len()));
+            rebuilt.push_str(value);
+        }
+        rebuilt.push('}');
+
+        let err = match serde_json::from_str::<T>(&rebuilt) {
+            Ok(_) => break,
+            Err(err) => err,
+        };
+        let message = message(&err);
+        let missing = missing_field(&message).map(|field| field.to_string());
+        let error_offset = line_column_to_offset(&rebuilt, &err);
+        let index = match missing {
+            Some(_) => None,
+            None => segments
+                .iter()
+                .rposition(|(start, _)| *start <= error_offset),
+        };
+
+        if let Some((replaced, next)) = replacing {
+            if index == Some(replaced) {
+                if next < PLACEHOLDERS.len() && !message.starts_with("unknown field") {
+                    fields[replaced].1 = PLACEHOLDERS[next].to_string();
+                    replacing = Some((replaced, next + 1));
+                } else {
+                    fields.remove(replaced);
+                    replacing = None;
+                }
+                continue;
+            }
+        }
+
+        match (missing, index) {
+            (Some(field), _) => {
+                errors.push(Error {
+                    offset: object_offset,
+                    field: None,
+                    message,
+                });
+                fields.push((field, PLACEHOLDERS[0].to_string(), None));
+                replacing = Some((fields.len() - 1, 1));
+            }
+            (None, Some(index)) => {
+                let (key, value, value_offset) = &mut fields[index];
+                let within_value = error_offset
+                    .saturating_sub(segments[index].1)
+                    .min(value.len().saturating_sub(1));
+                errors.push(Error {
+                    offset: value_offset.map_or(object_offset, |offset| offset + within_value),
+                    field: Some(key.clone()),
+                    message,
+                });
+                *value = PLACEHOLDERS[0].to_string();
+                *value_offset = None;
+                replacing = Some((index, 1));
+            }
+            (None, None) => {
+                errors.push(Error {
+                    offset: object_offset,
+                    field: None,
+                    message,
+                });
+                break;
+            }
+        }
+    }
+    errors
+}
+
+fn missing_field(message: &str) -> Option<&str> {
+    let rest = message.strip_prefix("missing field `")?;
+    rest.find('`').map(|end| &rest[..end])
+}
+
+/// The error message without the position.
+fn message(err: &serde_json::Error) -> String {
+    let message = err.to_string();
+    match message.rfind(" at line ") {
+        Some(end) => message[..end].to_string(),
+        None => message,
+    }
+}
+
+/// Byte offset of a raw value that was borrowed from `text`.
+fn offset(text: &str, value: &RawValue) -> usize {
+    value.get().as_ptr() as usize - text.as_ptr() as usize
+}
+
+fn line_column_to_offset(text: &str, err: &serde_json::Error) -> usize {
+    let line_start: usize = text
+        .split('\n')
+        .take(err.line().saturating_sub(1))
+        .map(|line| line.len() + 1)
+        .sum();
+    (line_start + err.column().saturating_sub(1)).min(text.len())
+}
+
+/// One based line and column of a byte offset.
+fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map(|index| index + 1).unwrap_or(0) + 1;
+    (line, column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// (line, column, field, message) of every error.
+    fn errors<T: DeserializeOwned>(text: &str) -> Vec<(usize, usize, Option<String>, String)> {
+        let mut errors = object_errors::<T>(text, root(text).unwrap());
+        errors.sort_by_key(|error| error.offset);
+        errors
+            .into_iter()
+            .map(|error| {
+                let (line, column) = line_column(text, error.offset);
+                (line, column, error.field, error.message)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn valid_object_has_no_errors() {
+        let text = r#"{"playlists": [{"youtube": "PLabc", "position": 3}]}"#;
+        assert!(errors::<Playlists>(text).is_empty());
+    }
+
+    #[test]
+    fn every_error_is_reported_at_its_value() {
+        let text = "{\n  \"youtube_api_key\": 1,\n  \"store\": \"mysql\",\n  \"unknown\": true\n}";
+        let errors = errors::<Config>(text);
+        let positions: Vec<(usize, usize, Option<&str>)> = errors
+            .iter()
+            .map(|(line, column, field, _)| (*line, *column, field.as_deref()))
+            .collect();
+        assert_eq!(
+            positions,
+            vec![
+                (2, 22, Some("youtube_api_key")),
+                (3, 18, Some("store")),
+                (4, 14, Some("unknown")),
+            ]
+        );
+        assert!(errors[0].3.starts_with("invalid type: integer `1`"));
+        assert!(errors[1].3.starts_with("unknown variant `mysql`"));
+        assert!(errors[2].3.starts_with("unknown field `unknown`"));
+    }
+
+    #[test]
+    fn missing_fields_are_reported() {
+        let text = r#"{"version": 1}"#;
+        let errors = errors::<Playlists>(text);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].3, "missing field `playlists`");
+    }
+
+    #[test]
+    fn invalid_regular_expressions_are_reported() {
+        let text = r#"{"youtube": "PLabc", "filters": {"include_title": "(a"}}"#;
+        let errors = errors::<Playlist>(text);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].3.starts_with("invalid regular expression (a"));
+    }
+
+    #[test]
+    fn errors_of_all_playlist_entries_are_reported() {
+        let text = r#"{"playlists": [{"position": "a"}, {"mirror": 1}]}"#;
+        let errors = playlists_errors(text).unwrap();
+        assert_eq!(errors.len(), 2);
+        assert!(errors.iter().all(|error| error.field.is_some()));
+    }
+}
@@ -1,6 +1,78 @@
 use serde::Deserialize;
 
 pub const YOUTUBE_API_PLAYLIST_ITEMS: &str = "https://www.googleapis.com/youtube/v3/playlistItems";
+pub const YOUTUBE_API_PLAYLISTS: &str = "https://www.googleapis.com/youtube/v3/playlists";
+pub const YOUTUBE_API_CHANNELS: &str = "https://www.googleapis.com/youtube/v3/channels";
+pub const YOUTUBE_API_VIDEOS: &str = "https://www.googleapis.com/youtube/v3/videos";
+/// The most videos that can be requested at once.
+pub const VIDEOS_PER_REQUEST: usize = 50;
+
+/// The kinds of api requests.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Request {
+    PlaylistItems,
+    Playlists,
+    Videos,
+    Channels,
+}
+
+impl Request {
+    pub fn name(self) -> &'static str {
+        match self {
+            Request::PlaylistItems => "playlist items",
+            Request::Playlists => "playlist",
+            Request::Videos => "videos",
+            Request::Channels => "channel",
+        }
+    }
+
+    /// Playlists are requested again on every run while the other requests differ every time.
+    pub fn is_playlist(self) -> bool {
+        self == Request::PlaylistItems || self == Request::Playlists
+    }
+}
+
+/// Quota units used by a list request.
+pub const LIST_COST: u64 = 1;
+
+/// The day the quota is counted for. Youtube resets the quota at midnight Pacific time, daylight
+/// saving time is ignored.
+pub fn quota_day() -> String {
+    (chrono::Utc::now() - chrono::Duration::hours(8))
+        .format("%Y-%m-%d")
+        .to_string()
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: ErrorBody,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorBody {
+    #[serde(default)]
+    errors: Vec<ErrorItem>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorItem {
+    reason: String,
+}
+
+/// Whether the body of a 403 response says that the daily quota is used up.
+pub fn is_quota_exceeded(body: &str) -> bool {
+    serde_json::from_str::<ErrorResponse>(body)
+        .map(|response| {
+            response.error.errors.iter().any(|error| {
+                error.reason == "quotaExceeded" || error.reason == "dailyLimitExceeded"
+            })
+        })
+        .unwrap_or(false)
+}
+
+pub fn video_url(video_id: &str) -> String {
+    format!("https://youtube.com/watch?v={}", video_id)
+}
 
 pub fn make_playlist_items_url(
     id: &str,
@@ -17,10 +89,436 @@ pub fn make_playlist_items_url(
     )
 }
 
+pub fn make_playlists_url(
+    id: &str,
+    youtube_api_key: &str,
+) -> Result<reqwest::Url, url::ParseError> {
+    reqwest::Url::parse_with_params(
+        YOUTUBE_API_PLAYLISTS,
+        &[("key", youtube_api_key), ("id", id), ("part", "snippet")],
+    )
+}
+
+/// The playlist id in a playlist id or a Youtube url with a `list` parameter like
+/// `/playlist?list=ID`, `/watch?v=VIDEO&list=ID` or `youtu.be/VIDEO?list=ID`.
+pub fn parse_playlist_id(input: &str) -> Result<String, String> {
+    let input = input.trim();
+    let id = if input.contains('/') || input.contains('?') {
+        let url = if input.contains("://") {
+            input.to_string()
+        } else {
+            format!("https://{}", input)
+        };
+        let url = reqwest::Url::parse(&url)
+            .map_err(|err| format!("{} is not a valid url: {}", input, err))?;
+        let host = url.host_str().unwrap_or_default();
+        if host != "youtu.be" && host != "youtube.com" && !host.ends_with(".youtube.com") {
+            return Err(format!("{} is not a Youtube url", input));
+        }
+        url.query_pairs()
+            .find(|(name, _)| name == "list")
+            .map(|(_, id)| id.into_owned())
+            .ok_or_else(|| format!("the url {} does not contain a playlist", input))?
+    } else {
+        input.to_string()
+    };
+    if id.is_empty()
+        || !id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(format!("{} is not a valid Youtube playlist id", id));
+    }
+    Ok(id)
+}
+
+pub fn make_videos_url(
+    ids: &[&str],
+    youtube_api_key: &str,
+) -> Result<reqwest::Url, url::ParseError> {
+    reqwest::Url::parse_with_params(
+        YOUTUBE_API_VIDEOS,
+        &[
+            ("key", youtube_api_key),
+            ("id", &ids.join(",")),
+            ("part", "contentDetails,snippet,liveStreamingDetails"),
+            ("maxResults", "50"),
+        ],
+    )
+}
+
+/// The name of a Youtube video category.
+pub fn category_name(id: &str) -> Option<&'static str> {
+    let name = match id {
+        "1" => "Film & Animation",
+        "2" => "Autos & Vehicles",
+        "10" => "Music",
+        "15" => "Pets & Animals",
+        "17" => "Sports",
+        "19" => "Travel & Events",
+        "20" => "Gaming",
+        "22" => "People & Blogs",
+        "23" => "Comedy",
+        "24" => "Entertainment",
+        "25" => "News & Politics",
+        "26" => "Howto & Style",
+        "27" => "Education",
+        "28" => "Science & Technology",
+        "29" => "Nonprofits & Activism",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Seconds of an ISO 8601 duration like `PT1H2M3S` or `P1DT2H`.
+pub fn parse_duration(duration: &str) -> Option<u64> {
+    let rest = duration.strip_prefix('P')?;
+    let mut seconds = 0;
+    let mut number = String::new();
+    let mut in_time = false;
+    for c in rest.chars() {
+        match c {
+            'T' => in_time = true,
+            '0'..='9' => number.push(c),
+            unit => {
+                let value: u64 = number.parse().ok()?;
+                number.clear();
+                seconds += value
+                    * match (unit, in_time) {
+                        ('W', false) => 7 * 24 * 60 * 60,
+                        ('D', false) => 24 * 60 * 60,
+                        ('H', true) => 60 * 60,
+                        ('M', true) => 60,
+                        ('S', true) => 1,
+                        _ => return None,
+                    };
+            }
+        }
+    }
+    Some(seconds).filter(|_| number.is_empty())
+}
+
+/// A way to look up a channel with the channels endpoint.
+#[derive(Debug, PartialEq)]
+pub enum ChannelLookup {
+    Id(String),
+    Handle(String),
+    Username(String),
+}
+
+/// The lookups to try for a channel id (`UC...`), a handle (`@name`) or a Youtube channel url of
+/// the forms `/channel/ID`, `/@handle`, `/user/NAME`, `/c/NAME` and `/NAME`. Custom names cannot
+/// be looked up directly so they are tried as handle and as legacy user name. Empty if the input
+/// is not a channel.
+pub fn parse_channel(input: &str) -> Vec<ChannelLookup> {
+    let input = input.trim();
+    let path = if input.contains('/') {
+        let url = if input.contains("://") {
+            input.to_string()
+        } else {
+            format!("https://{}", input)
+        };
+        match reqwest::Url::parse(&url) {
+            Ok(url)
+                if matches!(
+                    url.host_str(),
+                    Some("youtube.com" | "www.youtube.com" | "m.youtube.com" | "youtu.be")
+                ) =>
+            {
+                url.path().to_string()
+            }
+            _ => return Vec::new(),
+        }
+    } else {
+        input.to_string()
+    };
+    let segments: Vec<&str> = path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect();
+    let lookups = match segments.as_slice() {
+        ["channel", id, ..] => vec![ChannelLookup::Id(id.to_string())],
+        ["user", name, ..] => vec![ChannelLookup::Username(name.to_string())],
+        ["c", name, ..] if !name.starts_with('@') => vec![
+            ChannelLookup::Handle(format!("@{}", name)),
+            ChannelLookup::Username(name.to_string()),
+        ],
+        [handle, ..] if handle.starts_with('@') => vec![ChannelLookup::Handle(handle.to_string())],
+        [id] if id.starts_with("UC") && id.len() == 24 => vec![ChannelLookup::Id(id.to_string())],
+        [name, ..] if !["c", "channel", "user", "watch", "playlist"].contains(name) => vec![
+            ChannelLookup::Handle(format!("@{}", name)),
+            ChannelLookup::Username(name.to_string()),
+        ],
+        _ => Vec::new(),
+    };
+    let valid = lookups.iter().all(|lookup| {
+        let name = match lookup {
+            ChannelLookup::Id(id) => id.as_str(),
+            ChannelLookup::Handle(handle) => &handle[1..],
+            ChannelLookup::Username(name) => name.as_str(),
+        };
+        !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+    });
+    if valid {
+        lookups
+    } else {
+        Vec::new()
+    }
+}
+
+pub fn make_channels_url(
+    lookup: &ChannelLookup,
+    youtube_api_key: &str,
+) -> Result<reqwest::Url, url::ParseError> {
+    let (name, value) = match lookup {
+        ChannelLookup::Id(id) => ("id", id),
+        ChannelLookup::Handle(handle) => ("forHandle", handle),
+        ChannelLookup::Username(name) => ("forUsername", name),
+    };
+    reqwest::Url::parse_with_params(
+        YOUTUBE_API_CHANNELS,
+        &[
+            ("key", youtube_api_key),
+            (name, value),
+            ("part", "contentDetails"),
+        ],
+    )
+}
+
+pub fn channel_url(lookup: &ChannelLookup) -> String {
+    match lookup {
+        ChannelLookup::Id(id) => format!("https://www.youtube.com/channel/{}", id),
+        ChannelLookup::Handle(handle) => format!("https://www.youtube.com/{}", handle),
+        ChannelLookup::Username(name) => format!("https://www.youtube.com/user/{}", name),
+    }
+}
+
+/// The output of `youtube-dl --flat-playlist -J`.
+#[derive(Debug, Deserialize)]
+struct FlatPlaylist {
+    id: Option<String>,
+    channel_id: Option<String>,
+    title: Option<String>,
+    description: Option<String>,
+    #[serde(default)]
+    thumbnails: Vec<Thumbnail>,
+    #[serde(default)]
+    entries: Vec<FlatEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FlatEntry {
+    id: String,
+    title: Option<String>,
+    description: Option<String>,
+}
+
+fn get_flat_playlist(url: &str, extra_args: &[&str]) -> Result<FlatPlaylist, String> {
+    let output = std::process::Command::new("youtube-dl")
+        .arg(url)
+        .args(["--flat-playlist", "-J"])
+        .args(extra_args)
+        .output()
+        .expect("failed to run youtube-dl");
+    if !output.status.success() {
+        return Err(format!(
+            "youtube-dl did not signal success: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    };
+    serde_json::from_slice(&output.stdout)
+        .map_err(|err| format!("failed to parse youtube-dl output: {}", err))
+}
+
+/// Whether a youtube-dl error says that the playlist or video cannot be accessed at all, so that
+/// trying again does not help.
+pub fn is_unavailable(err: &str) -> bool {
+    let err = err.to_lowercase();
+    let transient = ["temporarily unavailable", "service unavailable"];
+    ["does not exist", "is private", "unavailable"]
+        .iter()
+        .any(|reason| err.contains(reason))
+        && !transient.iter().any(|reason| err.contains(reason))
+}
+
+fn video_thumbnails(video_id: &str) -> Thumbnails {
+    let thumbnail = |name: &str| Thumbnail {
+        url: format!("https://i.ytimg.com/vi/{}/{}.jpg", video_id, name),
+    };
+    Thumbnails {
+        default: thumbnail("default"),
+        medium: Some(thumbnail("mqdefault")),
+        high: Some(thumbnail("hqdefault")),
+        standard: None,
+        maxres: None,
+    }
+}
+
+/// The metadata and all items of a playlist listed with youtube-dl instead of the api. The
+/// publishing dates of the videos are unknown.
+pub fn list_playlist_with_youtube_dl(id: &str) -> Result<(Playlist, Vec<PlaylistItem>), String> {
+    let playlist = get_flat_playlist(
+        &format!("https://www.youtube.com/playlist?list={}", id),
+        &[],
+    )?;
+    let items: Vec<PlaylistItem> = playlist
+        .entries
+        .into_iter()
+        .enumerate()
+        .map(|(position, entry)| PlaylistItem {
+            contentDetails: ContentDetails {
+                videoPublishedAt: String::new(),
+                videoId: entry.id.clone(),
+            },
+            snippet: Snippet {
+                title: entry.title.unwrap_or_default(),
+                description: entry.description.unwrap_or_default(),
+                position: position as u64,
+                publishedAt: String::new(),
+                thumbnails: video_thumbnails(&entry.id),
+            },
+        })
+        .collect();
+    let thumbnails = match (playlist.thumbnails.last(), items.first()) {
+        (None, Some(first)) => video_thumbnails(&first.contentDetails.videoId),
+        // An empty url for empty playlists without a thumbnail makes downloading it fail.
+        (thumbnail, _) => Thumbnails {
+            default: Thumbnail {
+                url: thumbnail
+                    .map(|thumbnail| thumbnail.url.clone())
+                    .unwrap_or_default(),
+            },
+            medium: None,
+            high: None,
+            standard: None,
+            maxres: None,
+        },
+    };
+    let metadata = Playlist {
+        snippet: PlaylistSnippet {
+            title: playlist.title.unwrap_or_else(|| id.to_string()),
+            description: playlist.description.unwrap_or_default(),
+            thumbnails,
+        },
+    };
+    Ok((metadata, items))
+}
+
+/// The uploads playlist of a channel found with youtube-dl instead of the api. Returns `None` if
+/// there is no such channel.
+pub fn get_uploads_playlist_with_youtube_dl(
+    lookup: &ChannelLookup,
+) -> Result<Option<String>, String> {
+    let channel = match get_flat_playlist(&channel_url(lookup), &["--playlist-items", "1"]) {
+        Ok(channel) => channel,
+        Err(err) if is_unavailable(&err) => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    Ok(channel
+        .channel_id
+        .or(channel.id)
+        .filter(|id| id.starts_with("UC"))
+        .map(|id| format!("UU{}", &id[2..])))
+}
+
+/// What is known about a video beyond its playlist item.
+#[derive(Clone, Debug, Default)]
+pub struct VideoDetails {
+    /// Seconds.
+    pub duration: Option<u64>,
+    /// The video is a running livestream. Both backends leave out livestreams that ended or have
+    /// not started yet.
+    pub live: bool,
+    /// The video is a premiere or livestream that has not started yet.
+    pub upcoming: bool,
+    /// Whether the video is higher than wide, if known.
+    pub vertical: Option<bool>,
+    pub published: Option<chrono::NaiveDate>,
+    pub tags: Vec<String>,
+    pub category_id: Option<String>,
+}
+
+impl VideoDetails {
+    /// Shorts are vertical videos of up to three minutes. If the orientation is unknown, only
+    /// videos of up to a minute count.
+    pub fn is_short(&self) -> bool {
+        match (self.duration, self.vertical) {
+            (Some(duration), Some(true)) => duration <= 180,
+            (Some(duration), None) => duration <= 60,
+            _ => false,
+        }
+    }
+}
+
+/// The output of `youtube-dl --dump-json`.
+#[derive(Debug, Deserialize)]
+struct VideoInfo {
+    duration: Option<f64>,
+    /// Only reported by yt-dlp.
+    live_status: Option<String>,
+    is_live: Option<bool>,
+    width: Option<u64>,
+    height: Option<u64>,
+    /// `YYYYMMDD`
+    upload_date: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+/// The details of a video according to youtube-dl.
+pub fn get_video_details(video_id: &str) -> Result<VideoDetails, String> {
+    let output = std::process::Command::new("youtube-dl")
+        .arg(video_url(video_id))
+        .arg("--dump-json")
+        .output()
+        .expect("failed to run youtube-dl");
+    if !output.status.success() {
+        // Videos that are not available yet cannot be inspected.
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if stderr.contains("Premieres in") || stderr.contains("live event will begin") {
+            return Ok(VideoDetails {
+                upcoming: true,
+                ..Default::default()
+            });
+        }
+        return Err(format!(
+            "youtube-dl did not signal success: {}",
+            stderr.trim()
+        ));
+    };
+    let info: VideoInfo = serde_json::from_slice(&output.stdout)
+        .map_err(|err| format!("failed to parse youtube-dl output: {}", err))?;
+    Ok(info.details())
+}
+
+impl VideoInfo {
+    fn details(self) -> VideoDetails {
+        let live_status = self.live_status.as_deref().unwrap_or_default();
+        VideoDetails {
+            duration: self.duration.map(|duration| duration.ceil() as u64),
+            // Like with the api, only streams that are running count.
+            live: live_status == "is_live" || self.is_live == Some(true),
+            upcoming: live_status == "is_upcoming",
+            vertical: self
+                .width
+                .zip(self.height)
+                .map(|(width, height)| height > width),
+            published: self
+                .upload_date
+                .and_then(|date| chrono::NaiveDate::parse_from_str(&date, "%Y%m%d").ok()),
+            tags: self.tags,
+            category_id: None,
+        }
+    }
+}
+
 pub fn download_audio(video_id: &str) -> Result<String, String> {
     let output = std::process::Command::new("youtube-dl")
-        .arg(format!("https://youtube.com/watch?v={}", video_id))
-        .args(&["-f", "bestaudio"])
+        .arg(video_url(video_id))
+        .args(["-f", "bestaudio"])
         .arg("--restrict-filenames")
         .output()
         .expect("failed to run youtube-dl");
@@ -51,31 +549,137 @@ pub fn download_audio(video_id: &str) -> Result<String, String> {
 #[allow(non_snake_case)]
 pub struct PlaylistItemsResource {
     pub nextPageToken: Option<String>,
+    #[allow(dead_code)]
     pub pageInfo: PageInfo,
     pub items: Vec<PlaylistItem>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct PlaylistsResource {
+    pub items: Vec<Playlist>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VideosResource {
+    #[serde(default)]
+    pub items: Vec<Video>,
+}
+
+#[derive(Debug, Deserialize)]
+#[allow(non_snake_case)]
+pub struct Video {
+    pub id: String,
+    pub snippet: VideoSnippet,
+    pub contentDetails: VideoContentDetails,
+    /// Only present for livestreams and premieres.
+    pub liveStreamingDetails: Option<LiveStreamingDetails>,
+}
+
+#[derive(Debug, Deserialize)]
+#[allow(non_snake_case)]
+pub struct LiveStreamingDetails {
+    pub actualStartTime: Option<String>,
+    pub actualEndTime: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[allow(non_snake_case)]
+pub struct VideoSnippet {
+    pub publishedAt: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub categoryId: Option<String>,
+    /// `none`, `live` or `upcoming`.
+    pub liveBroadcastContent: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VideoContentDetails {
+    /// ISO 8601 duration.
+    pub duration: String,
+}
+
+impl Video {
+    pub fn details(&self) -> VideoDetails {
+        VideoDetails {
+            duration: parse_duration(&self.contentDetails.duration),
+            // Finished livestreams cannot be told apart from premieres, so only streams that are
+            // running count.
+            live: self.snippet.liveBroadcastContent == "live"
+                || self.liveStreamingDetails.as_ref().is_some_and(|live| {
+                    live.actualStartTime.is_some() && live.actualEndTime.is_none()
+                }),
+            upcoming: self.snippet.liveBroadcastContent == "upcoming",
+            vertical: None,
+            published: chrono::DateTime::parse_from_rfc3339(&self.snippet.publishedAt)
+                .ok()
+                .map(|published| published.date_naive()),
+            tags: self.snippet.tags.clone(),
+            category_id: self.snippet.categoryId.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChannelsResource {
+    /// Missing if no channel was found.
+    #[serde(default)]
+    pub items: Vec<Channel>,
+}
+
+#[derive(Debug, Deserialize)]
+#[allow(non_snake_case)]
+pub struct Channel {
+    pub contentDetails: ChannelContentDetails,
+}
+
+#[derive(Debug, Deserialize)]
+#[allow(non_snake_case)]
+pub struct ChannelContentDetails {
+    pub relatedPlaylists: RelatedPlaylists,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RelatedPlaylists {
+    /// The playlist of all uploads of the channel, newest first.
+    pub uploads: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Playlist {
+    pub snippet: PlaylistSnippet,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct PlaylistSnippet {
+    pub title: String,
+    pub description: String,
+    pub thumbnails: Thumbnails,
+}
+
 #[derive(Debug, Deserialize)]
 #[allow(non_snake_case)]
 pub struct PageInfo {
+    #[allow(dead_code)]
     pub totalResults: u64,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize)]
 #[allow(non_snake_case)]
 pub struct PlaylistItem {
     pub contentDetails: ContentDetails,
     pub snippet: Snippet,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize)]
 #[allow(non_snake_case)]
 pub struct ContentDetails {
     pub videoId: String,
+    #[allow(dead_code)]
     pub videoPublishedAt: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize)]
 #[allow(non_snake_case)]
 pub struct Snippet {
     pub title: String,
@@ -85,7 +689,7 @@ pub struct Snippet {
     pub thumbnails: Thumbnails,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize)]
 #[allow(non_snake_case)]
 pub struct Thumbnails {
     pub default: Thumbnail,
@@ -108,8 +712,181 @@ impl Thumbnails {
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize)]
 #[allow(non_snake_case)]
 pub struct Thumbnail {
     pub url: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn playlist_ids_are_parsed() {
+        assert_eq!(parse_playlist_id(" PLabc-_1 "), Ok("PLabc-_1".to_string()));
+        assert_eq!(
+            parse_playlist_id("https://www.youtube.com/playlist?list=PLabc"),
+            Ok("PLabc".to_string())
+        );
+        assert_eq!(
+            parse_playlist_id("m.youtube.com/watch?v=video&list=PLabc&index=2"),
+            Ok("PLabc".to_string())
+        );
+        assert_eq!(
+            parse_playlist_id("https://youtu.be/video?list=PLabc"),
+            Ok("PLabc".to_string())
+        );
+    }
+
+    #[test]
+    fn invalid_playlist_ids_are_rejected() {
+        assert!(parse_playlist_id("https://example.com/playlist?list=PLabc").is_err());
+        assert!(parse_playlist_id("https://www.youtube.com/watch?v=video").is_err());
+        assert!(parse_playlist_id("https://www.youtube.com/playlist?list=").is_err());
+        assert!(parse_playlist_id("PL abc").is_err());
+        assert!(parse_playlist_id("").is_err());
+    }
+
+    #[test]
+    fn unavailable_playlists_are_recognized() {
+        assert!(is_unavailable(
+            "youtube-dl did not signal success: ERROR: The playlist does not exist."
+        ));
+        assert!(is_unavailable("ERROR: This playlist is private"));
+        assert!(is_unavailable("ERROR: Video unavailable"));
+        assert!(!is_unavailable(
+            "ERROR: HTTP Error 503: Service Unavailable"
+        ));
+        assert!(!is_unavailable("ERROR: Unable to download webpage"));
+    }
+
+    #[test]
+    fn durations_are_parsed() {
+        assert_eq!(parse_duration("PT0S"), Some(0));
+        assert_eq!(parse_duration("PT4M13S"), Some(253));
+        assert_eq!(parse_duration("PT1H2S"), Some(3602));
+        assert_eq!(parse_duration("P1DT1M"), Some(86460));
+        assert_eq!(parse_duration("P1W"), Some(604800));
+        assert_eq!(parse_duration("P0D"), Some(0));
+        assert_eq!(parse_duration("PT"), Some(0));
+    }
+
+    #[test]
+    fn invalid_durations_are_rejected() {
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("4M13S"), None);
+        assert_eq!(parse_duration("PT4M13"), None);
+        assert_eq!(parse_duration("PTS"), None);
+        assert_eq!(parse_duration("P1M"), None);
+        assert_eq!(parse_duration("PT1D"), None);
+    }
+
+    #[test]
+    fn shorts_are_recognized() {
+        let details = |duration, vertical| VideoDetails {
+            duration,
+            vertical,
+            ..Default::default()
+        };
+        assert!(details(Some(180), Some(true)).is_short());
+        assert!(!details(Some(181), Some(true)).is_short());
+        assert!(!details(Some(30), Some(false)).is_short());
+        assert!(details(Some(60), None).is_short());
+        assert!(!details(Some(61), None).is_short());
+        assert!(!details(None, Some(true)).is_short());
+    }
+
+    #[test]
+    fn only_running_livestreams_are_live() {
+        let video = |broadcast: &str, live: serde_json::Value| -> Video {
+            serde_json::from_value(serde_json::json!({
+                "id": "id",
+                "snippet": {"publishedAt": "", "liveBroadcastContent": broadcast},
+                "contentDetails": {"duration": "PT1M"},
+                "liveStreamingDetails": live
+            }))
+            .unwrap()
+        };
+        let started = serde_json::json!({"actualStartTime": "2021-01-01T00:00:00Z"});
+        let ended = serde_json::json!({
+            "actualStartTime": "2021-01-01T00:00:00Z",
+            "actualEndTime": "2021-01-01T01:00:00Z"
+        });
+        assert!(video("live", serde_json::Value::Null).details().live);
+        assert!(video("none", started).details().live);
+        assert!(!video("none", ended).details().live);
+        assert!(!video("upcoming", serde_json::json!({})).details().live);
+        assert!(!video("none", serde_json::Value::Null).details().live);
+    }
+
+    #[test]
+    fn youtube_dl_only_reports_running_livestreams_as_live() {
+        let info = |info: serde_json::Value| -> VideoDetails {
+            serde_json::from_value::<VideoInfo>(info).unwrap().details()
+        };
+        assert!(info(serde_json::json!({"live_status": "is_live"})).live);
+        assert!(info(serde_json::json!({"is_live": true})).live);
+        let ended = info(serde_json::json!({"live_status": "was_live", "was_live": true}));
+        assert!(!ended.live);
+        assert!(!info(serde_json::json!({"live_status": "post_live"})).live);
+        let upcoming = info(serde_json::json!({"live_status": "is_upcoming"}));
+        assert!(!upcoming.live);
+        assert!(upcoming.upcoming);
+        assert!(!info(serde_json::json!({"live_status": "not_live", "is_live": false})).live);
+    }
+
+    fn custom(name: &str) -> Vec<ChannelLookup> {
+        vec![
+            ChannelLookup::Handle(format!("@{}", name)),
+            ChannelLookup::Username(name.to_string()),
+        ]
+    }
+
+    #[test]
+    fn channel_ids_and_handles_are_parsed() {
+        let id = "UCabcdefghijklmnopqrstuv";
+        assert_eq!(parse_channel(id), vec![ChannelLookup::Id(id.to_string())]);
+        assert_eq!(
+            parse_channel(" @some.name "),
+            vec![ChannelLookup::Handle("@some.name".to_string())]
+        );
+        assert_eq!(parse_channel("name"), custom("name"));
+    }
+
+    #[test]
+    fn channel_urls_are_parsed() {
+        assert_eq!(
+            parse_channel("https://www.youtube.com/channel/UC123/videos"),
+            vec![ChannelLookup::Id("UC123".to_string())]
+        );
+        assert_eq!(
+            parse_channel("youtube.com/@handle"),
+            vec![ChannelLookup::Handle("@handle".to_string())]
+        );
+        assert_eq!(
+            parse_channel("https://m.youtube.com/user/legacy"),
+            vec![ChannelLookup::Username("legacy".to_string())]
+        );
+        assert_eq!(
+            parse_channel("https://www.youtube.com/c/custom"),
+            custom("custom")
+        );
+        assert_eq!(
+            parse_channel("https://youtube.com/custom/videos"),
+            custom("custom")
+        );
+    }
+
+    #[test]
+    fn invalid_channels_are_rejected() {
+        assert_eq!(parse_channel("https://www.youtube.com/c/@handle"), vec![]);
+        assert_eq!(parse_channel("https://www.youtube.com/channel"), vec![]);
+        assert_eq!(parse_channel("https://www.youtube.com/watch?v=abc"), vec![]);
+        assert_eq!(parse_channel("https://example.com/@handle"), vec![]);
+        assert_eq!(parse_channel("https://notyoutube.com/@handle"), vec![]);
+        assert_eq!(parse_channel("@"), vec![]);
+        assert_eq!(parse_channel("some name"), vec![]);
+        assert_eq!(parse_channel(""), vec![]);
+    }
+}